@@ -0,0 +1,124 @@
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, EncodingKey, Validation};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::SubiloError;
+
+/// Keys the agent verifies tokens with: the shared secret for HS512 tokens
+/// and public keys for RS256 and EdDSA tokens, identified by the token `kid`.
+#[derive(Clone, Default)]
+pub struct Keys {
+    secret: Option<String>,
+    public: Arc<HashMap<String, (Algorithm, DecodingKey)>>,
+}
+
+impl Keys {
+    pub fn new(secret: Option<String>) -> Self {
+        Self {
+            secret,
+            public: Arc::default(),
+        }
+    }
+
+    /// Adds an RSA or Ed25519 public key from a PEM file, its `kid` is the
+    /// file name without extension.
+    pub fn add_public_key(&mut self, path: &str) -> Result<(), SubiloError> {
+        let kid = key_id(path);
+        let pem = read_key_file(path)?;
+
+        let key = DecodingKey::from_ed_pem(&pem)
+            .map(|key| (Algorithm::EdDSA, key))
+            .or_else(|_| DecodingKey::from_rsa_pem(&pem).map(|key| (Algorithm::RS256, key)))
+            .map_err(|err| SubiloError::InvalidKey {
+                path: path.to_owned(),
+                source: err,
+            })?;
+
+        Arc::make_mut(&mut self.public).insert(kid, key);
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool { self.secret.is_none() && self.public.is_empty() }
+
+    pub fn decode<T: DeserializeOwned>(&self, token: &str) -> Result<T, SubiloError> {
+        let header =
+            decode_header(token).map_err(|err| SubiloError::Authenticate { source: err })?;
+
+        let (algorithm, key) = match (header.alg, &header.kid) {
+            (Algorithm::HS512, _) => match &self.secret {
+                Some(secret) => (
+                    Algorithm::HS512,
+                    DecodingKey::from_secret(secret.as_bytes()),
+                ),
+                None => return Err(SubiloError::UnknownKey { kid: None }),
+            },
+            (_, Some(kid)) => match self.public.get(kid) {
+                Some((algorithm, key)) => (*algorithm, key.clone()),
+                None => {
+                    return Err(SubiloError::UnknownKey {
+                        kid: Some(kid.clone()),
+                    })
+                }
+            },
+            (_, None) => return Err(SubiloError::UnknownKey { kid: None }),
+        };
+
+        // The key decides the algorithm, whatever the header claims
+        decode::<T>(token, &key, &Validation::new(algorithm))
+            .map(|data| data.claims)
+            .map_err(|err| SubiloError::Authenticate { source: err })
+    }
+}
+
+/// Key tokens are signed with: the shared secret or an RSA or Ed25519
+/// private key
+pub struct SigningKey {
+    pub(super) algorithm: Algorithm,
+    pub(super) kid: Option<String>,
+    pub(super) key: EncodingKey,
+}
+
+impl SigningKey {
+    pub fn from_secret(secret: &str) -> Self {
+        Self {
+            algorithm: Algorithm::HS512,
+            kid: None,
+            key: EncodingKey::from_secret(secret.as_bytes()),
+        }
+    }
+
+    /// Reads a PEM private key, its `kid` defaults to the file name without
+    /// extension, matching the public key file name on the agents.
+    pub fn from_private_key_file(path: &str, kid: Option<String>) -> Result<Self, SubiloError> {
+        let pem = read_key_file(path)?;
+
+        let (algorithm, key) = EncodingKey::from_ed_pem(&pem)
+            .map(|key| (Algorithm::EdDSA, key))
+            .or_else(|_| EncodingKey::from_rsa_pem(&pem).map(|key| (Algorithm::RS256, key)))
+            .map_err(|err| SubiloError::InvalidKey {
+                path: path.to_owned(),
+                source: err,
+            })?;
+
+        Ok(Self {
+            algorithm,
+            kid: Some(kid.unwrap_or_else(|| key_id(path))),
+            key,
+        })
+    }
+}
+
+fn read_key_file(path: &str) -> Result<Vec<u8>, SubiloError> {
+    let path = shellexpand::tilde(path).into_owned();
+    fs::read(&path).map_err(|err| SubiloError::ReadKeyFile { path, source: err })
+}
+
+fn key_id(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_owned())
+}
@@ -0,0 +1,268 @@
+use actix::Addr;
+use actix_web::dev::Payload;
+use actix_web::dev::ServiceRequest;
+use actix_web::HttpRequest;
+use actix_web_httpauth::extractors::bearer::{BearerAuth, Config};
+use actix_web_httpauth::extractors::AuthenticationError;
+use futures::future;
+use jsonwebtoken::{encode, Header};
+use serde::{Deserialize, Serialize};
+
+use crate::database::{self, Database};
+use crate::Context;
+use crate::SubiloError;
+
+mod keys;
+mod permissions;
+mod query;
+
+pub use keys::{Keys, SigningKey};
+pub use permissions::{Permission, Permissions};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct User {
+    permissions: Vec<Permission>,
+}
+
+impl User {
+    pub fn has_project_permission(&self, permission: Permissions, project: &str) -> bool {
+        self.permissions
+            .iter()
+            .any(|granted| granted.allows(permission, project))
+    }
+
+    /// Patterns of the projects the user has the permission on, `None` when
+    /// it has it on every project
+    pub fn project_patterns(&self, permission: Permissions) -> Option<Vec<String>> {
+        let mut patterns = vec![];
+        for granted in self
+            .permissions
+            .iter()
+            .filter(|granted| granted.grants(permission))
+        {
+            match granted.project() {
+                Some(pattern) => patterns.push(pattern.to_owned()),
+                None => return None,
+            }
+        }
+
+        Some(patterns)
+    }
+}
+
+impl actix_web::FromRequest for User {
+    type Config = ();
+    type Error = SubiloError;
+    type Future = future::Ready<Result<Self, SubiloError>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let token_result = req
+            .app_data::<actix_web::web::Data<Context>>()
+            .ok_or(SubiloError::ReadContext {})
+            .and_then(|context| {
+                let token = req
+                    .headers()
+                    .get("authorization")
+                    .and_then(|header| header.to_str().ok())
+                    .map(|s| s.replace("Bearer ", ""))
+                    .ok_or(SubiloError::MissingToken {})?;
+
+                context.keys.decode::<Claims>(&token)
+            });
+
+        match token_result {
+            Ok(claims) => future::ok(claims.user),
+            Err(err) => future::err(err),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    exp: usize,  // Expiration time (as UTC timestamp)
+    iat: usize,  // Issued at (as UTC timestamp)
+    iss: String, // Issuer
+    // Token id, missing on tokens created before they were recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    jti: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    user: User,
+}
+
+/// A token as recorded in the `tokens` table
+#[derive(Debug, Serialize)]
+pub struct TokenRecord {
+    pub id: String,
+    pub label: String,
+    pub permissions: String,
+    pub created_at: String,
+    pub expires_at: String,
+    pub revoked_at: Option<String>,
+}
+
+pub struct Token {
+    pub jwt: String,
+    pub record: TokenRecord,
+}
+
+pub fn create_token(
+    signing_key: &SigningKey,
+    label: &str,
+    permissions: Vec<Permission>,
+    duration: i64,
+) -> Result<Token, SubiloError> {
+    let mut header = Header::new(signing_key.algorithm);
+    header.kid = signing_key.kid.clone();
+    let id = nanoid::nanoid!();
+    let created_at = chrono::Utc::now();
+    let expires_at = created_at + chrono::Duration::minutes(duration);
+
+    let record = TokenRecord {
+        id: id.clone(),
+        label: label.to_owned(),
+        permissions: permissions
+            .iter()
+            .map(|permission| permission.to_string())
+            .collect::<Vec<_>>()
+            .join(","),
+        created_at: created_at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        expires_at: expires_at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        revoked_at: None,
+    };
+
+    let claims = Claims {
+        exp: expires_at.timestamp() as usize,
+        iat: created_at.timestamp() as usize,
+        iss: "subilo:agent".to_owned(),
+        jti: Some(id),
+        label: Some(label.to_owned()).filter(|label| !label.is_empty()),
+        user: User { permissions },
+    };
+
+    let jwt = encode(&header, &claims, &signing_key.key)
+        .map_err(|err| SubiloError::Authenticate { source: err })?;
+
+    Ok(Token { jwt, record })
+}
+
+pub async fn record_token(
+    database: &Addr<Database>,
+    record: &TokenRecord,
+) -> Result<(), SubiloError> {
+    let insert = database::Execute {
+        query: query::INSERT_TOKEN.to_owned(),
+        params: vec![
+            record.id.clone(),
+            record.label.clone(),
+            record.permissions.clone(),
+            record.created_at.clone(),
+            record.expires_at.clone(),
+        ],
+    };
+
+    database
+        .send(insert)
+        .await
+        .map_err(|err| SubiloError::DatabaseActor { source: err })?
+        .map_err(|err| SubiloError::DatabaseQuery { source: err })?;
+
+    Ok(())
+}
+
+pub async fn list_tokens(database: &Addr<Database>) -> Result<Vec<TokenRecord>, SubiloError> {
+    let query = database::Query {
+        query: query::GET_TOKENS.to_owned(),
+        params: vec![],
+        map_result: |row| {
+            Ok(TokenRecord {
+                id: row.get(0)?,
+                label: row.get(1)?,
+                permissions: row.get(2)?,
+                created_at: row.get(3)?,
+                expires_at: row.get(4)?,
+                revoked_at: row.get(5)?,
+            })
+        },
+    };
+
+    database
+        .send(query)
+        .await
+        .map_err(|err| SubiloError::DatabaseActor { source: err })?
+        .map_err(|err| SubiloError::DatabaseQuery { source: err })
+}
+
+/// Revokes a token, returns false when it was already revoked. Tokens that
+/// are not recorded, created elsewhere, are revoked by their id.
+pub async fn revoke_token(database: &Addr<Database>, id: &str) -> Result<bool, SubiloError> {
+    let revoked_at = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+
+    for query in &[query::REVOKE_TOKEN, query::INSERT_REVOCATION] {
+        let revoke = database::Execute {
+            query: (*query).to_owned(),
+            params: vec![id.to_owned(), revoked_at.clone()],
+        };
+
+        let revoked = database
+            .send(revoke)
+            .await
+            .map_err(|err| SubiloError::DatabaseActor { source: err })?
+            .map_err(|err| SubiloError::DatabaseQuery { source: err })?;
+
+        if revoked > 0 {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+async fn is_revoked(database: &Addr<Database>, id: &str) -> Result<bool, SubiloError> {
+    let query = database::Query {
+        query: query::GET_TOKEN_REVOKED_AT.to_owned(),
+        params: vec![id.to_owned()],
+        map_result: |row| row.get::<_, Option<String>>(0),
+    };
+
+    let revoked_at = database
+        .send(query)
+        .await
+        .map_err(|err| SubiloError::DatabaseActor { source: err })?
+        .map_err(|err| SubiloError::DatabaseQuery { source: err })?;
+
+    Ok(revoked_at
+        .into_iter()
+        .any(|revoked_at| revoked_at.is_some()))
+}
+
+pub async fn validator(
+    req: ServiceRequest,
+    credentials: BearerAuth,
+) -> Result<ServiceRequest, actix_web::Error> {
+    let context = req
+        .app_data::<Context>()
+        .expect("Failed to read context on validator");
+
+    let config = req
+        .app_data::<Config>()
+        .map(|data| data.get_ref().clone())
+        .unwrap_or_default();
+
+    let claims = match context.keys.decode::<Claims>(credentials.token()) {
+        Ok(claims) => claims,
+        Err(err) => {
+            debug!("Rejecting token. {}", err);
+            return Err(AuthenticationError::from(config).into());
+        }
+    };
+
+    if let Some(id) = &claims.jti {
+        if is_revoked(&context.database, id).await? {
+            debug!("Rejecting revoked token '{}'", id);
+            return Err(AuthenticationError::from(config).into());
+        }
+    }
+
+    Ok(req)
+}
@@ -0,0 +1,145 @@
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::SubiloError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Permissions {
+    /// Deploy projects
+    JobWrite,
+    /// See jobs and their status
+    JobRead,
+    /// Cancel queued and running jobs
+    JobCancel,
+    /// Read job logs
+    LogRead,
+    /// Every permission on every project
+    Admin,
+}
+
+impl Permissions {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permissions::JobWrite => "job:write",
+            Permissions::JobRead => "job:read",
+            Permissions::JobCancel => "job:cancel",
+            Permissions::LogRead => "log:read",
+            Permissions::Admin => "admin",
+        }
+    }
+
+    fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "job:write" => Some(Permissions::JobWrite),
+            "job:read" => Some(Permissions::JobRead),
+            "job:cancel" => Some(Permissions::JobCancel),
+            "log:read" => Some(Permissions::LogRead),
+            "admin" => Some(Permissions::Admin),
+            _ => None,
+        }
+    }
+}
+
+/// A permission granted by a token, for every project or only for the
+/// projects matching a glob pattern: `job:write` or `job:write:foo-*`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Permission {
+    kind: Permissions,
+    project: Option<String>,
+}
+
+impl Permission {
+    /// Whether it grants the permission, on some project at least. Deploying
+    /// a project also allows cancelling its jobs, as it did before
+    /// `job:cancel` existed.
+    pub fn grants(&self, kind: Permissions) -> bool {
+        self.kind == Permissions::Admin
+            || self.kind == kind
+            || (self.kind == Permissions::JobWrite && kind == Permissions::JobCancel)
+    }
+
+    /// Pattern of the projects it is limited to
+    pub fn project(&self) -> Option<&str> {
+        match self.kind {
+            Permissions::Admin => None,
+            _ => self.project.as_deref(),
+        }
+    }
+
+    /// Whether it grants the permission on the project
+    pub fn allows(&self, kind: Permissions, project: &str) -> bool {
+        if !self.grants(kind) {
+            return false;
+        }
+
+        match self.project() {
+            None => true,
+            Some(pattern) => glob::Pattern::new(pattern)
+                .map(|pattern| pattern.matches(project))
+                .unwrap_or(false),
+        }
+    }
+}
+
+impl From<Permissions> for Permission {
+    fn from(kind: Permissions) -> Self {
+        Self {
+            kind,
+            project: None,
+        }
+    }
+}
+
+impl FromStr for Permission {
+    type Err = SubiloError;
+
+    fn from_str(permission: &str) -> Result<Self, Self::Err> {
+        let invalid = || SubiloError::InvalidPermission {
+            permission: permission.to_owned(),
+        };
+
+        let mut parts = permission.splitn(3, ':');
+        let kind = match (parts.next(), parts.next()) {
+            (Some("admin"), None) => "admin".to_owned(),
+            (Some(resource), Some(action)) => format!("{}:{}", resource, action),
+            _ => return Err(invalid()),
+        };
+
+        let kind = Permissions::parse(&kind).ok_or_else(invalid)?;
+        let project = match parts.next() {
+            Some(pattern) if is_valid_pattern(pattern) => Some(pattern.to_owned()),
+            Some(_) => return Err(invalid()),
+            None => None,
+        };
+
+        Ok(Self { kind, project })
+    }
+}
+
+/// Project patterns only have `*` and `?` wildcards, the jobs database (SQLite
+/// `GLOB`) and the agent match them the same way
+fn is_valid_pattern(pattern: &str) -> bool {
+    !pattern.is_empty() && !pattern.contains(['[', ']']) && glob::Pattern::new(pattern).is_ok()
+}
+
+impl TryFrom<String> for Permission {
+    type Error = SubiloError;
+
+    fn try_from(permission: String) -> Result<Self, Self::Error> { permission.parse() }
+}
+
+impl From<Permission> for String {
+    fn from(permission: Permission) -> Self { permission.to_string() }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.project {
+            Some(project) => write!(f, "{}:{}", self.kind.as_str(), project),
+            None => write!(f, "{}", self.kind.as_str()),
+        }
+    }
+}
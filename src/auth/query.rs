@@ -0,0 +1,34 @@
+pub const INSERT_TOKEN: &str = "
+    INSERT INTO tokens (id, label, permissions, created_at, expires_at)
+    VALUES (?1, ?2, ?3, ?4, ?5)
+";
+
+pub const GET_TOKENS: &str = "
+    SELECT id, label, permissions, created_at, expires_at, revoked_at
+    FROM tokens
+    ORDER BY created_at, id
+";
+
+pub const REVOKE_TOKEN: &str = "
+    UPDATE tokens
+    SET revoked_at = ?2
+    WHERE id = ?1 AND revoked_at IS NULL
+";
+
+/// Revokes a token that is not recorded in this database, e.g. created with
+/// another database or signed by a private key
+pub const INSERT_REVOCATION: &str = "
+    INSERT OR IGNORE INTO revocations (id, revoked_at)
+    SELECT ?1, ?2
+    WHERE NOT EXISTS (SELECT 1 FROM tokens WHERE id = ?1)
+";
+
+pub const GET_TOKEN_REVOKED_AT: &str = "
+    SELECT revoked_at
+    FROM tokens
+    WHERE id = ?1
+    UNION ALL
+    SELECT revoked_at
+    FROM revocations
+    WHERE id = ?1
+";
@@ -0,0 +1,342 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use toml::value::{Table, Value};
+
+use crate::{config, core, env, JobsConfig, ProjectsInfo};
+
+// Keep in sync with `core::Project`, `core::ProjectInfo` and `hooks::Hook`
+const TOP_LEVEL_KEYS: &[&str] = &["agent", "projects"];
+const PROJECT_KEYS: &[&str] = &[
+    "name",
+    "path",
+    "commands",
+    "timeout",
+    "concurrency",
+    "params",
+    "env",
+    "env_file",
+    "redact",
+    "webhook_secret",
+    "github",
+    "gitlab",
+    "gitea",
+    "forgejo",
+    "home",
+    "ci",
+    "repo",
+];
+const COMMAND_KEYS: &[&str] = &["command", "timeout"];
+const HOOK_KEYS: &[&str] = &["repository", "branches", "tags", "secret"];
+const HOOKS: &[&str] = &["github", "gitlab", "gitea", "forgejo"];
+const URLS: &[&str] = &["home", "ci", "repo"];
+
+/// Problem found in a `.subilorc` file
+#[derive(Debug, PartialEq)]
+pub struct Issue {
+    /// Line of the problem, starting at 1, when it can be told
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Checks the content of a `.subilorc` file, beyond what is needed to parse
+/// it: duplicate project names, missing paths, empty commands, unknown keys,
+/// invalid URLs and invalid variable or param names.
+pub fn check(content: &str) -> Vec<Issue> {
+    let mut issues = Vec::new();
+
+    let value: Value = match toml::from_str(content) {
+        Ok(value) => value,
+        Err(err) => {
+            issues.push(parse_issue(&err));
+            return issues;
+        }
+    };
+
+    if let Err(err) = toml::from_str::<JobsConfig>(content) {
+        issues.push(parse_issue(&err));
+    } else if let Err(err) = toml::from_str::<ProjectsInfo>(content) {
+        issues.push(parse_issue(&err));
+    }
+    if let Err(err) = config::Config::parse(content) {
+        issues.push(Issue {
+            line: None,
+            message: err.to_string(),
+        });
+    }
+
+    let lines = Lines::new(content);
+    let root = match value.as_table() {
+        Some(root) => root,
+        None => return issues,
+    };
+
+    for key in root.keys() {
+        if !TOP_LEVEL_KEYS.contains(&key.as_str()) {
+            issues.push(Issue {
+                line: lines.top_level_key(key),
+                message: format!("Unknown key '{}'", key),
+            });
+        }
+    }
+
+    let projects = root.get("projects").and_then(Value::as_array);
+    let mut names: HashMap<&str, Option<usize>> = HashMap::new();
+
+    for (index, project) in projects.into_iter().flatten().enumerate() {
+        let project = match project.as_table() {
+            Some(project) => project,
+            None => continue,
+        };
+        let line = |key: &str| lines.project_key(index, key);
+        let name = project.get("name").and_then(Value::as_str).unwrap_or("");
+
+        if !name.is_empty() {
+            match names.get(name) {
+                Some(first) => issues.push(Issue {
+                    line: line("name"),
+                    message: match first {
+                        Some(first) => {
+                            format!("Project '{}' is already defined at line {}", name, first)
+                        }
+                        None => format!("Project '{}' is defined more than once", name),
+                    },
+                }),
+                None => {
+                    names.insert(name, line("name"));
+                }
+            }
+        }
+
+        for key in project.keys() {
+            if !PROJECT_KEYS.contains(&key.as_str()) {
+                issues.push(Issue {
+                    line: line(key),
+                    message: format!("Unknown key '{}' in project '{}'", key, name),
+                });
+            }
+        }
+
+        if let Some(path) = project.get("path").and_then(Value::as_str) {
+            let expanded = shellexpand::tilde(path);
+            let expanded = Path::new(expanded.as_ref());
+            let message = if !expanded.exists() {
+                Some(format!(
+                    "Path '{}' of project '{}' does not exist",
+                    path, name
+                ))
+            } else if !expanded.is_dir() {
+                Some(format!(
+                    "Path '{}' of project '{}' is not a directory",
+                    path, name
+                ))
+            } else {
+                None
+            };
+
+            if let Some(message) = message {
+                issues.push(Issue {
+                    line: line("path"),
+                    message,
+                });
+            }
+        }
+
+        if let Some(commands) = project.get("commands").and_then(Value::as_array) {
+            if commands.is_empty() {
+                issues.push(Issue {
+                    line: line("commands"),
+                    message: format!("Project '{}' has no commands", name),
+                });
+            }
+
+            for command in commands {
+                let (command, table) = match command {
+                    Value::String(command) => (Some(command.as_str()), None),
+                    Value::Table(table) => {
+                        (table.get("command").and_then(Value::as_str), Some(table))
+                    }
+                    _ => continue,
+                };
+
+                if command.is_none_or(|command| command.trim().is_empty()) {
+                    issues.push(Issue {
+                        line: line("commands"),
+                        message: format!("Project '{}' has an empty command", name),
+                    });
+                }
+
+                for key in table.into_iter().flat_map(Table::keys) {
+                    if !COMMAND_KEYS.contains(&key.as_str()) {
+                        issues.push(Issue {
+                            line: line("commands"),
+                            message: format!(
+                                "Unknown key '{}' in a command of project '{}'",
+                                key, name
+                            ),
+                        });
+                    }
+                }
+            }
+        }
+
+        let env = project.get("env").and_then(Value::as_table);
+        for key in env.into_iter().flat_map(Table::keys) {
+            if !env::is_valid_key(key) {
+                issues.push(Issue {
+                    line: line("env"),
+                    message: format!(
+                        "Invalid environment variable name '{}' in project '{}'",
+                        key, name
+                    ),
+                });
+            }
+        }
+
+        let params = project.get("params").and_then(Value::as_array);
+        for param in params.into_iter().flatten().filter_map(Value::as_str) {
+            if !core::is_valid_param(param) {
+                issues.push(Issue {
+                    line: line("params"),
+                    message: format!("Invalid param name '{}' in project '{}'", param, name),
+                });
+            }
+        }
+
+        for hook in HOOKS {
+            let table = match project.get(*hook).and_then(Value::as_table) {
+                Some(table) => table,
+                None => continue,
+            };
+
+            for key in table.keys() {
+                if !HOOK_KEYS.contains(&key.as_str()) {
+                    issues.push(Issue {
+                        line: line(hook),
+                        message: format!("Unknown key '{}' in {} of project '{}'", key, hook, name),
+                    });
+                }
+            }
+        }
+
+        for key in URLS {
+            let value = match project.get(*key).and_then(Value::as_str) {
+                Some(value) => value,
+                None => continue,
+            };
+
+            // Repositories can also be cloned over ssh:// or git://
+            let valid = url::Url::parse(value)
+                .map(|url| {
+                    url.has_host()
+                        && (*key == "repo" || url.scheme() == "http" || url.scheme() == "https")
+                })
+                .unwrap_or(false);
+            if !valid {
+                issues.push(Issue {
+                    line: line(key),
+                    message: format!("Invalid {} URL '{}' in project '{}'", key, value, name),
+                });
+            }
+        }
+    }
+
+    issues.sort_by_key(|issue| issue.line);
+    issues
+}
+
+fn parse_issue(err: &toml::de::Error) -> Issue {
+    let message = err.to_string();
+    // The line is reported apart
+    let message = match (err.line_col(), message.rfind(" at line ")) {
+        (Some(_), Some(position)) => message[..position].to_owned(),
+        _ => message,
+    };
+
+    Issue {
+        line: err.line_col().map(|(line, _)| line + 1),
+        message,
+    }
+}
+
+/// Finds the lines of keys. TOML values do not keep their position, so the
+/// lines are looked up in the text, by the `[[projects]]` headers.
+struct Lines<'a> {
+    lines: Vec<&'a str>,
+    /// Index of the `[[projects]]` header lines
+    projects: Vec<usize>,
+}
+
+impl<'a> Lines<'a> {
+    fn new(content: &'a str) -> Self {
+        let lines: Vec<&str> = content.lines().collect();
+        let projects = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| header(line) == Some("[projects]"))
+            .map(|(index, _)| index)
+            .collect();
+
+        Self { lines, projects }
+    }
+
+    fn top_level_key(&self, key: &str) -> Option<usize> {
+        let end = self.projects.first().copied().unwrap_or(self.lines.len());
+        self.find_key(0, end, key, key)
+    }
+
+    fn project_key(&self, index: usize, key: &str) -> Option<usize> {
+        let start = *self.projects.get(index)?;
+        let end = self
+            .projects
+            .get(index + 1)
+            .copied()
+            .unwrap_or(self.lines.len());
+        self.find_key(start + 1, end, key, &format!("projects.{}", key))
+    }
+
+    /// Line of `key = ...` or of a `[table]` header, before any other table
+    fn find_key(&self, start: usize, end: usize, key: &str, table: &str) -> Option<usize> {
+        let mut in_table = false;
+
+        for (index, line) in self.lines[start..end].iter().enumerate() {
+            if let Some(header) = header(line) {
+                if header.trim_matches(|c| c == '[' || c == ']').trim() == table {
+                    return Some(start + index + 1);
+                }
+                in_table = true;
+                continue;
+            }
+
+            let assigned = line
+                .split('=')
+                .next()
+                .map(|name| name.trim().trim_matches('"'))
+                .filter(|_| line.contains('='));
+            if !in_table && assigned == Some(key) {
+                return Some(start + index + 1);
+            }
+        }
+
+        None
+    }
+}
+
+/// Content of a table header line, without its outer brackets
+fn header(line: &str) -> Option<&str> {
+    let line = line.split('#').next().unwrap_or("").trim();
+    if line.starts_with('[') && line.ends_with(']') {
+        Some(&line[1..line.len() - 1])
+    } else {
+        None
+    }
+}
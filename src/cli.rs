@@ -1,4 +1,4 @@
-pub fn ask<'a, 'b>(subilo_path: &'a str) -> clap::App<'a, 'b> {
+pub fn ask<'a, 'b>() -> clap::App<'a, 'b> {
     clap::App::new(env!("CARGO_PKG_NAME"))
         .version(env!("CARGO_PKG_VERSION"))
         .about(env!("CARGO_PKG_DESCRIPTION"))
@@ -7,7 +7,20 @@ pub fn ask<'a, 'b>(subilo_path: &'a str) -> clap::App<'a, 'b> {
             clap::Arg::with_name("secret")
                 .short("s")
                 .long("secret")
-                .help("Secret to generate and authenticate the token")
+                .help("Secret to generate and authenticate the token. Visible to other processes, prefer --secret-file or SUBILO_SECRET")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("secret-file")
+                .long("secret-file")
+                .help("File containing the secret")
+                .conflicts_with("secret")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("agent-config")
+                .long("agent-config")
+                .help("Path to a file with an [agent] section, read instead of the one in .subilorc")
                 .takes_value(true),
         )
         .arg(
@@ -27,28 +40,134 @@ pub fn ask<'a, 'b>(subilo_path: &'a str) -> clap::App<'a, 'b> {
                         .default_value(".subilorc")
                         .takes_value(true),
                 )
+                .arg(
+                    clap::Arg::with_name("bind")
+                        .short("b")
+                        .long("bind")
+                        .help("Address to listen on: an IPv4 or IPv6 address, with optional port, or a unix:/path/to/socket. Can be repeated. Defaults to 127.0.0.1")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("socket-mode")
+                        .long("socket-mode")
+                        .help("Octal file mode of the Unix sockets, e.g. 660")
+                        .takes_value(true),
+                )
                 .arg(
                     clap::Arg::with_name("port")
                         .short("p")
                         .long("port")
-                        .help("Custom server port")
-                        .default_value("8787")
+                        .help("Custom server port. Defaults to 8787")
                         .takes_value(true),
                 )
                 .arg(
                     clap::Arg::with_name("logs-dir")
                         .short("l")
                         .long("logs-dir")
-                        .help("Custom logs directory")
-                        .default_value("./logs")
+                        .help("Custom logs directory. Defaults to ./logs")
                         .takes_value(true),
                 )
                 .arg(
                     clap::Arg::with_name("database")
                         .short("d")
                         .long("database")
-                        .help("Database directory")
-                        .default_value(subilo_path)
+                        .help("Database directory. Defaults to ~/.subilo")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("public-key")
+                        .long("public-key")
+                        .help("RSA or Ed25519 public key PEM file to verify tokens with, named after its kid. Can be repeated")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("public-status")
+                        .long("public-status")
+                        .help("Serve /healthz and /info without a token"),
+                )
+                .arg(
+                    clap::Arg::with_name("max-jobs")
+                        .long("max-jobs")
+                        .help("Maximum number of jobs running at the same time. Defaults to 4")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("retention-days")
+                        .long("retention-days")
+                        .help("Days to keep finished jobs and their logs. Kept forever by default")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("tls-cert")
+                        .long("tls-cert")
+                        .help("PEM certificate chain file to serve HTTPS with")
+                        .requires("tls-key")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("tls-key")
+                        .long("tls-key")
+                        .help("PEM private key file of the TLS certificate")
+                        .requires("tls-cert")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            clap::App::new("check")
+                .about("Check the .subilorc file for mistakes, exits with status 1 if it finds any")
+                .arg(
+                    clap::Arg::with_name("config")
+                        .short("c")
+                        .long("config")
+                        .help("Path to .subilorc file")
+                        .default_value(".subilorc")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            clap::App::new("dry-run")
+                .about("Show how a project would be deployed, with its working directory, environment and commands, without running them")
+                .arg(
+                    clap::Arg::with_name("project")
+                        .help("Name of the project")
+                        .required(true),
+                )
+                .arg(
+                    clap::Arg::with_name("config")
+                        .short("c")
+                        .long("config")
+                        .help("Path to .subilorc file")
+                        .default_value(".subilorc")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("ref")
+                        .long("ref")
+                        .help("Git ref, as sent by the webhook")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("sha")
+                        .long("sha")
+                        .help("Commit sha, as sent by the webhook")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("tag")
+                        .long("tag")
+                        .help("Tag, as sent by the webhook")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("param")
+                        .long("param")
+                        .help("Webhook param as NAME=value. Can be repeated")
+                        .multiple(true)
+                        .number_of_values(1)
                         .takes_value(true),
                 ),
         )
@@ -59,7 +178,7 @@ pub fn ask<'a, 'b>(subilo_path: &'a str) -> clap::App<'a, 'b> {
                     clap::Arg::with_name("permissions")
                         .short("p")
                         .long("permissions")
-                        .help("Token permissions")
+                        .help("Comma separated token permissions, optionally scoped to projects: job:write:foo-*")
                         .default_value("")
                         .takes_value(true),
                 )
@@ -71,5 +190,51 @@ pub fn ask<'a, 'b>(subilo_path: &'a str) -> clap::App<'a, 'b> {
                         .default_value("525600") // 60 * 24 * 365
                         .takes_value(true),
                 )
+                .arg(
+                    clap::Arg::with_name("label")
+                        .short("l")
+                        .long("label")
+                        .help("Token label, to tell it apart when listing tokens")
+                        .default_value("")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("config")
+                        .short("c")
+                        .long("config")
+                        .help("Path to .subilorc file, to read the secret from its [agent] section")
+                        .default_value(".subilorc")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("private-key")
+                        .long("private-key")
+                        .help("RSA or Ed25519 private key PEM file to sign the token with, instead of the secret")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("kid")
+                        .long("kid")
+                        .help("Id of the signing key. Defaults to the private key file name without extension")
+                        .requires("private-key")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("database")
+                        .long("database")
+                        .help("Database directory where tokens are recorded. Defaults to the agent database, ~/.subilo")
+                        .global(true)
+                        .takes_value(true),
+                )
+                .subcommand(clap::App::new("list").about("List the created tokens"))
+                .subcommand(
+                    clap::App::new("revoke")
+                        .about("Revoke a token, the agent rejects it from then on")
+                        .arg(
+                            clap::Arg::with_name("id")
+                                .help("Id of the token to revoke")
+                                .required(true),
+                        ),
+                )
         )
 }
@@ -0,0 +1,211 @@
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::str::FromStr;
+use std::{env, fs};
+
+use crate::errors::SubiloError;
+
+pub const DEFAULT_BIND: &str = "127.0.0.1";
+pub const DEFAULT_PORT: u16 = 8787;
+pub const DEFAULT_LOGS_DIR: &str = "./logs";
+pub const DEFAULT_DATABASE: &str = "~/.subilo";
+pub const DEFAULT_MAX_JOBS: usize = 4;
+
+/// Agent settings, from the `[agent]` section of `.subilorc` or of a separate
+/// agent configuration file. Command line arguments take precedence.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Addresses the agent listens on, IPs with optional port or `unix:`
+    /// socket paths
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub bind: Vec<String>,
+    /// File mode of the Unix sockets, an octal string like "660"
+    #[serde(
+        default,
+        deserialize_with = "deserialize_mode",
+        serialize_with = "serialize_mode"
+    )]
+    pub socket_mode: Option<u32>,
+    pub port: Option<u16>,
+    pub logs_dir: Option<String>,
+    /// Database directory
+    pub database: Option<String>,
+    pub secret: Option<String>,
+    /// File containing the secret
+    pub secret_file: Option<String>,
+    /// Public key files to verify tokens with
+    #[serde(default)]
+    pub public_keys: Vec<String>,
+    /// Serve `/healthz` and `/info` without a token
+    pub public_status: Option<bool>,
+    /// Maximum number of jobs running at the same time
+    pub max_jobs: Option<usize>,
+    /// Days finished jobs and their logs are kept, forever when not set
+    pub retention_days: Option<u32>,
+    pub tls: Option<TlsConfig>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM certificate chain
+    pub cert: String,
+    /// PEM private key
+    pub key: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubiloRC {
+    #[serde(default)]
+    agent: Config,
+}
+
+impl Config {
+    /// Reads the `[agent]` section of a `.subilorc` file
+    pub fn read(path: &str) -> Result<Self, SubiloError> {
+        let content =
+            fs::read_to_string(path).map_err(|err| SubiloError::ReadSubiloRC { source: err })?;
+
+        Self::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> Result<Self, SubiloError> {
+        let subilorc: SubiloRC =
+            toml::from_str(content).map_err(|err| SubiloError::ParseSubiloRC { source: err })?;
+
+        Ok(subilorc.agent)
+    }
+
+    /// Overrides the settings with the ones given as `serve` arguments
+    pub fn apply_args(&mut self, matches: &clap::ArgMatches<'_>) -> Result<(), SubiloError> {
+        let value = |name: &str| matches.value_of(name).map(|value| value.to_owned());
+
+        if let Some(bind) = matches.values_of("bind") {
+            self.bind = bind.map(|address| address.to_owned()).collect();
+        }
+        if let Some(mode) = matches.value_of("socket-mode") {
+            let mode = parse_mode(mode).ok_or_else(|| SubiloError::InvalidArgument {
+                name: "socket-mode".to_owned(),
+                value: mode.to_owned(),
+            })?;
+            self.socket_mode = Some(mode);
+        }
+        self.port = parse_arg(matches, "port")?.or(self.port);
+        self.logs_dir = value("logs-dir").or_else(|| self.logs_dir.take());
+        self.database = value("database").or_else(|| self.database.take());
+        self.max_jobs = parse_arg(matches, "max-jobs")?.or(self.max_jobs);
+        self.retention_days = parse_arg(matches, "retention-days")?.or(self.retention_days);
+
+        if let Some(public_keys) = matches.values_of("public-key") {
+            self.public_keys = public_keys.map(|path| path.to_owned()).collect();
+        }
+
+        if matches.is_present("public-status") {
+            self.public_status = Some(true);
+        }
+
+        // clap requires both TLS arguments together
+        if let (Some(cert), Some(key)) = (value("tls-cert"), value("tls-key")) {
+            self.tls = Some(TlsConfig { cert, key });
+        }
+
+        Ok(())
+    }
+
+    /// Finds the secret, in order of precedence, in the `--secret` or
+    /// `--secret-file` arguments, the `SUBILO_SECRET` environment variable or
+    /// the `secret` or `secret_file` settings.
+    pub fn secret(&self, matches: &clap::ArgMatches<'_>) -> Result<Option<String>, SubiloError> {
+        if let Some(secret) = matches.value_of("secret") {
+            debug!("Using the secret from the --secret argument");
+            return Ok(Some(secret.to_owned()));
+        }
+
+        if let Some(path) = matches.value_of("secret-file") {
+            debug!("Using the secret from the file '{}'", path);
+            return read_secret_file(path);
+        }
+
+        if let Some(secret) = env::var("SUBILO_SECRET").ok().filter(|s| !s.is_empty()) {
+            debug!("Using the secret from the SUBILO_SECRET environment variable");
+            return Ok(Some(secret));
+        }
+
+        if let Some(secret) = &self.secret {
+            debug!("Using the secret from the agent configuration");
+            return Ok(Some(secret.clone()));
+        }
+
+        if let Some(path) = &self.secret_file {
+            debug!("Using the secret from the file '{}'", path);
+            return read_secret_file(path);
+        }
+
+        Ok(None)
+    }
+}
+
+fn read_secret_file(path: &str) -> Result<Option<String>, SubiloError> {
+    let path = shellexpand::tilde(path).into_owned();
+    let secret = fs::read_to_string(&path)
+        .map_err(|err| SubiloError::ReadSecretFile { path, source: err })?;
+
+    // Files usually end with a new line, which is not part of the secret
+    let secret = secret.trim_end_matches(['\n', '\r']);
+    Ok(Some(secret.to_owned()).filter(|secret| !secret.is_empty()))
+}
+
+fn parse_arg<T: FromStr>(
+    matches: &clap::ArgMatches<'_>,
+    name: &str,
+) -> Result<Option<T>, SubiloError> {
+    match matches.value_of(name) {
+        Some(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| SubiloError::InvalidArgument {
+                name: name.to_owned(),
+                value: value.to_owned(),
+            }),
+        None => Ok(None),
+    }
+}
+
+/// Parses an octal file mode, like `chmod` does
+fn parse_mode(mode: &str) -> Option<u32> {
+    u32::from_str_radix(mode, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+}
+
+fn deserialize_mode<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    let mode = String::deserialize(deserializer)?;
+    parse_mode(&mode).map(Some).ok_or_else(|| {
+        de::Error::custom(format!(
+            "invalid socket_mode '{}', expected an octal mode like \"660\"",
+            mode
+        ))
+    })
+}
+
+fn serialize_mode<S: Serializer>(mode: &Option<u32>, serializer: S) -> Result<S::Ok, S::Error> {
+    match mode {
+        Some(mode) => serializer.serialize_str(&format!("{:o}", mode)),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Accepts a single value or a list
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
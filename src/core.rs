@@ -1,50 +1,281 @@
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
-use std::process::Command;
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
 use std::{str, thread};
 
+use crate::env;
 use crate::errors::SubiloError;
-use crate::job;
+use crate::hooks;
+use crate::job::{self, JobStatus};
+use crate::queue;
 use crate::Context;
 
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(rename_all = "kebab-case")]
-pub enum MetadataStatus {
-    Started,
-    Succeeded,
-    Failed,
-}
-
-#[derive(Debug, Deserialize, Serialize)]
-pub struct Metadata {
-    pub name: String,
-    pub status: MetadataStatus,
-    pub started_at: String,
-    pub ended_at: Option<String>,
-}
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const TERMINATE_GRACE_PERIOD: Duration = Duration::from_secs(10);
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Project {
     pub name: String,
     pub path: String,
-    pub commands: Vec<String>,
+    pub commands: Vec<ProjectCommand>,
+    /// Time limit for the whole job, in seconds
+    pub timeout: Option<u64>,
+    #[serde(default)]
+    pub concurrency: queue::Policy,
+    /// Names of the webhook `params` the project accepts
+    #[serde(default)]
+    pub params: Vec<String>,
+    /// Environment variables set for every command
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// File with environment variables, relative to the project's path
+    pub env_file: Option<String>,
+    /// Names of the variables whose values are masked in the job log
+    #[serde(default)]
+    pub redact: Vec<String>,
+    /// Secret of the signed webhook, see `hooks::signed`
+    pub webhook_secret: Option<String>,
+    /// GitHub repository whose pushes deploy the project
+    pub github: Option<hooks::Hook>,
+    /// GitLab repository whose pushes deploy the project
+    pub gitlab: Option<hooks::Hook>,
+    /// Gitea or Forgejo repository whose pushes deploy the project
+    #[serde(alias = "forgejo")]
+    pub gitea: Option<hooks::Hook>,
 }
 
 impl Project {
     pub fn description(&self) -> String { format!("Project '{}' at {}\n", self.name, self.path) }
 
-    pub fn commands_to_json(&self) -> Result<String, serde_json::error::Error> {
-        serde_json::to_string(&self.commands)
+    /// The commands as a JSON list, with the redactor's secrets masked
+    pub fn commands_to_json(
+        &self,
+        redactor: &job::Redactor,
+    ) -> Result<String, serde_json::error::Error> {
+        let commands: Vec<String> = self
+            .commands
+            .iter()
+            .map(|c| redactor.redact_str(c.command()))
+            .collect();
+        serde_json::to_string(&commands)
+    }
+}
+
+/// A project command, either a plain string or a table with options:
+/// `{ command = "docker-compose pull", timeout = 300 }`.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum ProjectCommand {
+    Plain(String),
+    Detailed {
+        command: String,
+        /// Time limit for the command, in seconds
+        timeout: Option<u64>,
+    },
+}
+
+impl ProjectCommand {
+    pub fn command(&self) -> &str {
+        match self {
+            ProjectCommand::Plain(command) => command,
+            ProjectCommand::Detailed { command, .. } => command,
+        }
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        match self {
+            ProjectCommand::Plain(_) => None,
+            ProjectCommand::Detailed { timeout, .. } => timeout.map(Duration::from_secs),
+        }
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+pub fn is_valid_param(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Data sent along with a webhook, exported to the commands as `SUBILO_*`
+/// environment variables.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct Trigger {
+    #[serde(rename = "ref")]
+    pub git_ref: Option<String>,
+    pub sha: Option<String>,
+    pub tag: Option<String>,
+    #[serde(default)]
+    pub params: BTreeMap<String, String>,
+}
+
+impl Trigger {
+    pub fn validate(&self, project: &Project) -> Result<(), SubiloError> {
+        for name in self.params.keys() {
+            if !project.params.contains(name) {
+                return Err(SubiloError::InvalidTrigger {
+                    message: format!("Param '{}' is not allowed by the project", name),
+                });
+            }
+
+            if !is_valid_param(name) {
+                return Err(SubiloError::InvalidTrigger {
+                    message: format!(
+                        "Param '{}' can only contain letters, digits and underscores",
+                        name
+                    ),
+                });
+            }
+        }
+
+        let values = self
+            .git_ref
+            .iter()
+            .chain(self.sha.iter())
+            .chain(self.tag.iter())
+            .chain(self.params.values());
+        for value in values {
+            if value.contains('\0') {
+                return Err(SubiloError::InvalidTrigger {
+                    message: "Values cannot contain null characters".to_owned(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn environment(&self) -> Vec<(String, String)> {
+        let mut env = vec![];
+
+        if let Some(git_ref) = &self.git_ref {
+            env.push(("SUBILO_REF".to_owned(), git_ref.clone()));
+        }
+        if let Some(sha) = &self.sha {
+            env.push(("SUBILO_SHA".to_owned(), sha.clone()));
+        }
+        if let Some(tag) = &self.tag {
+            env.push(("SUBILO_TAG".to_owned(), tag.clone()));
+        }
+        for (name, value) in &self.params {
+            env.push((
+                format!("SUBILO_PARAM_{}", name.to_uppercase()),
+                value.clone(),
+            ));
+        }
+
+        env
+    }
+}
+
+/// A project deployment requested by a webhook
+#[derive(Debug, Clone)]
+pub struct Deployment {
+    pub project: Project,
+    pub trigger: Trigger,
+    /// Only write to the log what would be run
+    pub dry_run: bool,
+}
+
+/// Environment variables of a job and the values to mask from its log
+#[derive(Debug, Default)]
+pub struct Environment {
+    pub vars: Vec<(String, String)>,
+    pub secrets: Vec<String>,
+}
+
+impl Deployment {
+    /// Environment variables of the job commands, on top of the agent's own.
+    /// Later sources take precedence: the project's `env_file`, its `env`, the
+    /// webhook data and the job variables.
+    ///
+    /// Secrets are every value from the `env_file` and the values of the
+    /// variables named in the project's `redact` list.
+    pub fn environment(&self, job_id: &str, job_name: &str) -> Result<Environment, SubiloError> {
+        let mut env = BTreeMap::new();
+        let mut secrets = vec![];
+
+        if let Some(env_file) = &self.project.env_file {
+            let path = shellexpand::tilde(&self.project.path).into_owned();
+            let env_file = Path::new(&path).join(shellexpand::tilde(env_file).as_ref());
+            let file_env = env::read_env_file(&env_file.to_string_lossy())?;
+            secrets.extend(file_env.iter().map(|(_key, value)| value.clone()));
+            env.extend(file_env);
+        }
+
+        for (key, value) in &self.project.env {
+            if !env::is_valid_key(key) {
+                return Err(SubiloError::InvalidEnvKey { key: key.clone() });
+            }
+            env.insert(key.clone(), value.clone());
+        }
+
+        env.extend(self.trigger.environment());
+        env.insert("SUBILO_JOB_ID".to_owned(), job_id.to_owned());
+        env.insert("SUBILO_JOB_NAME".to_owned(), job_name.to_owned());
+        env.insert("SUBILO_PROJECT".to_owned(), self.project.name.clone());
+
+        for key in &self.project.redact {
+            let value = env.get(key).cloned().or_else(|| std::env::var(key).ok());
+            secrets.extend(value);
+        }
+
+        Ok(Environment {
+            vars: env.into_iter().collect(),
+            secrets,
+        })
+    }
+
+    /// Describes how the commands would be run by `run_command`: in which
+    /// directory, with which environment and with which time limits. Secrets
+    /// are not masked here.
+    pub fn plan(&self, env: &Environment) -> String {
+        let project = &self.project;
+        let path = shellexpand::tilde(&project.path).into_owned();
+        let mut plan = String::from("Dry run, no command is executed\n");
+
+        let missing = if Path::new(&path).is_dir() {
+            ""
+        } else {
+            " (not a directory)"
+        };
+        plan.push_str(&format!("Working directory: {}{}\n", path, missing));
+
+        plan.push_str("Environment, on top of the agent's own:\n");
+        for (key, value) in &env.vars {
+            plan.push_str(&format!("  {}={}\n", key, value));
+        }
+
+        if let Some(timeout) = project.timeout {
+            plan.push_str(&format!("Job timeout: {} seconds\n", timeout));
+        }
+
+        plan.push_str("Commands:\n");
+        for command in &project.commands {
+            plan.push_str(&format!("$ sh -c {}\n", shell_quote(command.command())));
+            if let Some(timeout) = command.timeout() {
+                plan.push_str(&format!("  Timeout: {} seconds\n", timeout.as_secs()));
+            }
+        }
+
+        plan
+    }
+}
+
+/// Quotes a value for `sh`, within single quotes
+fn shell_quote(value: &str) -> String { format!("'{}'", value.replace('\'', "'\\''")) }
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ProjectInfo {
     pub name: String,
     pub home: Option<String>,
     pub ci: Option<String>,
     pub repo: Option<String>,
-    pub commands: Vec<String>,
+    pub commands: Vec<ProjectCommand>,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -53,93 +284,288 @@ pub enum RunError {
     #[error("[FATAL] Failed to clone log file, {}", source)]
     CloneLogFile { source: std::io::Error },
 
+    #[error("[FATAL] Failed to read command output, {}", source)]
+    ReadOutput { source: std::io::Error },
+
     #[error("[FATAL] Failed to execute as child process: {}", source)]
     ExecuteCommand { source: std::io::Error },
+
+    #[error("Command cancelled")]
+    Cancelled,
+
+    #[error("Command timed out")]
+    TimedOut,
 }
 
 pub fn run_command(
     path: &str,
     command: &str,
+    env: &[(String, String)],
+    timeout: Option<Duration>,
     witness: &job::Witness,
-) -> Result<std::process::Output, RunError> {
-    let stdout = witness
-        .try_clone_log()
+) -> Result<ExitStatus, RunError> {
+    let stdout_log = witness
+        .log_writer()
         .map_err(|err| RunError::CloneLogFile { source: err })?;
-    let stderr = witness
-        .try_clone_log()
+    let stderr_log = witness
+        .log_writer()
         .map_err(|err| RunError::CloneLogFile { source: err })?;
 
-    Command::new("sh")
+    // The command runs in its own process group so that everything it starts
+    // can be signaled at once when the job is cancelled.
+    let mut child = Command::new("sh")
         .arg("-c")
         .arg(command)
-        .stdout(stdout)
-        .stderr(stderr)
+        .envs(env.iter().map(|(key, value)| (key, value)))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
         .current_dir(path)
+        .process_group(0)
         .spawn()
-        .map_err(|err| RunError::ExecuteCommand { source: err })?
-        .wait_with_output()
-        .map_err(|err| RunError::ExecuteCommand { source: err })
+        .map_err(|err| RunError::ExecuteCommand { source: err })?;
+
+    // Output goes through the log writers so secrets are masked before
+    // reaching the log file
+    let (copied, copies) = mpsc::channel();
+    let stdout = child.stdout.take().expect("Command stdout is piped");
+    let stderr = child.stderr.take().expect("Command stderr is piped");
+    copy_output(stdout, stdout_log, copied.clone());
+    copy_output(stderr, stderr_log, copied);
+
+    let result = wait_command(&mut child, timeout, witness);
+
+    // Processes left running in the background can keep the output open,
+    // the copies are not waited for longer than the drain timeout
+    let deadline = Instant::now() + OUTPUT_DRAIN_TIMEOUT;
+    for _ in 0..2 {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match copies.recv_timeout(remaining) {
+            Ok(Err(err)) => return Err(RunError::ReadOutput { source: err }),
+            Ok(Ok(())) => {}
+            Err(_) => break,
+        }
+    }
+
+    result
 }
 
-pub fn run_project_deployment(
-    project: Project,
-    mut witness: job::Witness,
-) -> Result<(), SubiloError> {
+fn copy_output<R: Read + Send + 'static>(
+    output: R,
+    mut log: job::LogWriter,
+    copied: mpsc::Sender<std::io::Result<()>>,
+) {
+    thread::spawn(move || {
+        let result = log.copy_from(output);
+        let _ = copied.send(result);
+    });
+}
+
+fn wait_command(
+    child: &mut Child,
+    timeout: Option<Duration>,
+    witness: &job::Witness,
+) -> Result<ExitStatus, RunError> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+    loop {
+        let status = child
+            .try_wait()
+            .map_err(|err| RunError::ExecuteCommand { source: err })?;
+
+        if let Some(status) = status {
+            return Ok(status);
+        }
+
+        if witness.is_cancelled() {
+            terminate(child).map_err(|err| RunError::ExecuteCommand { source: err })?;
+            return Err(RunError::Cancelled);
+        }
+
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            terminate(child).map_err(|err| RunError::ExecuteCommand { source: err })?;
+            return Err(RunError::TimedOut);
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Sends SIGTERM to the child's process group and, if any process of the
+/// group is still alive after the grace period, SIGKILL. The child exiting is
+/// not enough, the processes it started in the background may ignore SIGTERM.
+fn terminate(child: &mut Child) -> std::io::Result<()> {
+    let group = -(child.id() as libc::pid_t);
+
+    unsafe { libc::kill(group, libc::SIGTERM) };
+
+    let deadline = Instant::now() + TERMINATE_GRACE_PERIOD;
+    while Instant::now() < deadline {
+        // The child is reaped first, as a zombie it still belongs to the group
+        if child.try_wait()?.is_some() && !is_group_alive(group) {
+            return Ok(());
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    unsafe { libc::kill(group, libc::SIGKILL) };
+    child.wait().map(|_status| ())
+}
+
+fn is_group_alive(group: libc::pid_t) -> bool {
+    let result = unsafe { libc::kill(group, 0) };
+    result == 0 || std::io::Error::last_os_error().raw_os_error() != Some(libc::ESRCH)
+}
+
+fn run_commands(
+    deployment: &Deployment,
+    witness: &mut job::Witness,
+) -> Result<JobStatus, SubiloError> {
+    let project = &deployment.project;
+    let path = shellexpand::tilde(&project.path).into_owned();
+    let env = match deployment.environment(witness.id(), witness.name()) {
+        Ok(env) => env,
+        Err(err) => {
+            witness.report_error(&err)?;
+            return Ok(JobStatus::Failed);
+        }
+    };
+    witness.set_redactor(job::Redactor::new(env.secrets.clone()));
+
+    if deployment.dry_run {
+        if witness.is_cancelled() {
+            witness.report_command_cancelled()?;
+            return Ok(JobStatus::Cancelled);
+        }
+        witness.report_plan(&deployment.plan(&env))?;
+        return Ok(JobStatus::Succeeded);
+    }
+
+    let env = env.vars;
+    let job_timeout = project.timeout.map(Duration::from_secs);
+    let job_deadline = job_timeout.map(|timeout| Instant::now() + timeout);
+
     for command in &project.commands {
+        if witness.is_cancelled() {
+            witness.report_command_cancelled()?;
+            return Ok(JobStatus::Cancelled);
+        }
+
+        let command_timeout = command.timeout();
+        let job_remaining =
+            job_deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        // The job's limit applies when it is tighter than the command's own
+        let job_limited = match (command_timeout, job_remaining) {
+            (Some(command_timeout), Some(job_remaining)) => job_remaining < command_timeout,
+            (None, Some(_)) => true,
+            _ => false,
+        };
+        let timeout = if job_limited {
+            job_remaining
+        } else {
+            command_timeout
+        };
+
+        let command = command.command();
         debug!("Running command: {}", &command);
 
         witness.report_command(command)?;
 
-        let path = shellexpand::tilde(&project.path).into_owned();
-
-        match run_command(&path, command, &witness) {
-            Ok(output) => {
-                if output.status.success() {
+        match run_command(&path, command, &env, timeout, witness) {
+            Ok(status) => {
+                if status.success() {
                     witness.report_command_success()?
                 } else {
-                    witness.report_command_error_by_code(output.status.code())?;
-                    break;
+                    witness.report_command_error_by_code(status.code())?;
+                    return Ok(JobStatus::Failed);
                 }
             }
+            Err(RunError::Cancelled) => {
+                witness.report_command_cancelled()?;
+                return Ok(JobStatus::Cancelled);
+            }
+            Err(RunError::TimedOut) => {
+                let message = if job_limited {
+                    format!(
+                        "Job timed out after {} seconds",
+                        job_timeout.unwrap_or_default().as_secs()
+                    )
+                } else {
+                    format!(
+                        "Command timed out after {} seconds",
+                        command_timeout.unwrap_or_default().as_secs()
+                    )
+                };
+                witness.report_command_timed_out(&message)?;
+                return Ok(JobStatus::TimedOut);
+            }
             Err(err) => {
                 witness.report_command_error(err)?;
-                break;
+                return Ok(JobStatus::Failed);
             }
         }
     }
 
-    Ok(())
+    Ok(JobStatus::Succeeded)
 }
 
-pub async fn spawn_job(project: Project, ctx: Context) -> Result<String, SubiloError> {
-    let job_name = create_job_name(&project.name);
-    let witness = job::Witness::new(job_name.clone(), project.clone(), ctx).await?;
+pub fn run_project_deployment(
+    deployment: Deployment,
+    mut witness: job::Witness,
+) -> Result<(), SubiloError> {
+    if witness.is_cancelled() {
+        witness.report_command_cancelled()?;
+        return witness.report_end(JobStatus::Cancelled);
+    }
+
+    if let Err(err) = witness.report_start() {
+        // Do not leave the job as queued if it could not be marked as started
+        witness.report_end(JobStatus::Failed)?;
+        return Err(err);
+    }
 
-    debug!(
-        "Spawning thread to run deployment for project {}",
-        &project.name
-    );
-    thread::spawn(move || {
-        let project_name = project.name.clone();
-        let result = run_project_deployment(project, witness);
-
-        match result {
-            Ok(_) => debug!(
-                "Deployment for project {} processed successfully",
-                project_name
-            ),
-            Err(err) => error!(
-                "Failed running deployment for project {}.\nWith error:\n{}",
-                project_name, err
-            ),
+    match run_commands(&deployment, &mut witness) {
+        Ok(status) => witness.report_end(status),
+        Err(err) => {
+            // Do not leave the job as running if reporting a command failed
+            witness.report_end(JobStatus::Failed)?;
+            Err(err)
         }
-    });
+    }
+}
+
+pub async fn spawn_job(deployment: Deployment, ctx: Context) -> Result<String, SubiloError> {
+    let project = &deployment.project;
+
+    // Dry runs do not wait for, nor cancel, the deployments of the project
+    if deployment.dry_run {
+        let witness = job::Witness::new(&deployment, ctx.clone()).await?;
+        let job_name = witness.name().to_owned();
+
+        debug!("Spawning thread to dry run project {}", &project.name);
+        thread::spawn(move || {
+            if let Err(err) = run_project_deployment(deployment, witness) {
+                error!("Failed to dry run deployment. Error: {}", err);
+            }
+        });
+
+        return Ok(job_name);
+    }
+
+    let _admission = ctx.queue.admit(project).await?;
+
+    let witness = job::Witness::new(&deployment, ctx.clone()).await?;
+    let job_name = witness.name().to_owned();
+
+    debug!("Queueing deployment for project {}", &project.name);
+    ctx.queue.submit(deployment, witness);
 
     Ok(job_name)
 }
 
-pub fn create_job_name(repository: &str) -> String {
+/// Job names are unique, several jobs of a project can be created in the
+/// same second
+pub fn create_job_name(repository: &str, job_id: &str) -> String {
     let repository = repository.replace('/', "-");
     let now = Utc::now().format("%Y-%m-%d--%H-%M-%S").to_string();
-    format!("{}_{}", repository, now)
+    format!("{}_{}_{}", repository, now, job_id)
 }
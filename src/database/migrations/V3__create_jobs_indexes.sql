@@ -0,0 +1,3 @@
+CREATE INDEX IF NOT EXISTS jobs_started_at_idx ON jobs (started_at, id);
+CREATE INDEX IF NOT EXISTS jobs_project_started_at_idx ON jobs (project, started_at, id);
+CREATE INDEX IF NOT EXISTS jobs_status_started_at_idx ON jobs (status, started_at, id);
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS nonces (
+    project TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (project, nonce)
+)
@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS revocations (
+    id TEXT PRIMARY KEY,
+    revoked_at TEXT NOT NULL
+)
@@ -0,0 +1,31 @@
+CREATE TABLE jobs_new (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    status TEXT NOT NULL,
+    project TEXT NOT NULL,
+    commands TEXT NOT NULL,
+    queued_at TEXT NOT NULL,
+    started_at TEXT,
+    ended_at TEXT,
+    dry_run INTEGER NOT NULL DEFAULT 0
+);
+
+-- Jobs created before queued_at was recorded were queued when they started.
+-- Jobs that never left the queue had started_at set to when they were queued
+INSERT INTO jobs_new (id, name, status, project, commands, queued_at, started_at, ended_at, dry_run)
+SELECT id, name, status, project, commands, COALESCE(queued_at, started_at),
+    CASE
+        WHEN status = 'queued' THEN NULL
+        WHEN status = 'cancelled' AND started_at = queued_at THEN NULL
+        ELSE started_at
+    END,
+    ended_at, dry_run
+FROM jobs;
+
+DROP TABLE jobs;
+
+ALTER TABLE jobs_new RENAME TO jobs;
+
+CREATE INDEX IF NOT EXISTS jobs_queued_at_idx ON jobs (queued_at, id);
+CREATE INDEX IF NOT EXISTS jobs_project_queued_at_idx ON jobs (project, queued_at, id);
+CREATE INDEX IF NOT EXISTS jobs_status_queued_at_idx ON jobs (status, queued_at, id);
@@ -15,7 +15,7 @@ pub struct Database {
 
 impl Database {
     pub fn new(path: &str) -> Self {
-        fs::create_dir_all(&path).expect("Failed to create database directory");
+        fs::create_dir_all(path).expect("Failed to create database directory");
 
         let database_path_buf = Path::new(path).join("subilo-database.db");
         let database_path = match database_path_buf.to_str() {
@@ -0,0 +1,66 @@
+use std::fs;
+
+use crate::errors::SubiloError;
+
+/// Reads a file of `KEY=value` lines. Blank lines and lines starting with `#`
+/// are skipped, keys can be prefixed with `export` and values can be wrapped
+/// in single or double quotes.
+pub fn read_env_file(path: &str) -> Result<Vec<(String, String)>, SubiloError> {
+    let content = fs::read_to_string(path).map_err(|err| SubiloError::ReadEnvFile {
+        path: path.to_owned(),
+        source: err,
+    })?;
+
+    parse_env_file(&content).map_err(|line| SubiloError::ParseEnvFile {
+        path: path.to_owned(),
+        line,
+    })
+}
+
+/// Parses the content of an env file, failing with the number of the first
+/// invalid line.
+fn parse_env_file(content: &str) -> Result<Vec<(String, String)>, usize> {
+    let mut env = vec![];
+
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = match line.find('=') {
+            Some(position) => (line[..position].trim(), line[position + 1..].trim()),
+            None => return Err(index + 1),
+        };
+
+        if !is_valid_key(key) {
+            return Err(index + 1);
+        }
+
+        env.push((key.to_owned(), unquote(value)));
+    }
+
+    Ok(env)
+}
+
+pub fn is_valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && !key.starts_with(|c: char| c.is_ascii_digit())
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn unquote(value: &str) -> String {
+    let quoted =
+        |quote: char| value.len() >= 2 && value.starts_with(quote) && value.ends_with(quote);
+
+    if quoted('\'') {
+        value[1..value.len() - 1].to_owned()
+    } else if quoted('"') {
+        value[1..value.len() - 1]
+            .replace("\\n", "\n")
+            .replace("\\\"", "\"")
+    } else {
+        value.to_owned()
+    }
+}
@@ -14,6 +14,12 @@ pub enum SubiloError {
     #[error("Failed to parse Subilo rc file, {}", source)]
     ParseSubiloRC { source: toml::de::Error },
 
+    #[error("Project {} is defined more than once", name)]
+    DuplicateProject { name: String },
+
+    #[error("Invalid value '{}' for --{}", value, name)]
+    InvalidArgument { name: String, value: String },
+
     #[error("Failed to create log directory, {}", source)]
     CreateLogDir { source: std::io::Error },
 
@@ -23,15 +29,78 @@ pub enum SubiloError {
     #[error("Failed to write log file, {}", source)]
     WriteLogFile { source: std::io::Error },
 
+    #[error("Failed to read log file, {}", source)]
+    ReadLogFile { source: std::io::Error },
+
     #[error("Failed to authenticate request, {}", source)]
     Authenticate { source: jsonwebtoken::errors::Error },
 
     #[error("Token missing")]
     MissingToken {},
 
+    #[error("Invalid permission '{}'", permission)]
+    InvalidPermission { permission: String },
+
+    #[error("No key to verify the token{}", kid.as_ref().map(|kid| format!(" '{}'", kid)).unwrap_or_default())]
+    UnknownKey { kid: Option<String> },
+
+    #[error("Failed to read secret file {}, {}", path, source)]
+    ReadSecretFile {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("Invalid bind address {}", address)]
+    InvalidBind { address: String },
+
+    #[error("Failed to read TLS file {}, {}", path, source)]
+    ReadTlsFile {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("Invalid TLS file {}, {}", path, message)]
+    InvalidTlsFile { path: String, message: String },
+
+    #[error("Failed to read key file {}, {}", path, source)]
+    ReadKeyFile {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("Invalid key file {}, {}", path, source)]
+    InvalidKey {
+        path: String,
+        source: jsonwebtoken::errors::Error,
+    },
+
     #[error("Failed to parse project commands to JSON format")]
     ParseProjectCommands { source: serde_json::error::Error },
 
+    #[error("Invalid job status transition from '{}' to '{}'", from, to)]
+    JobTransition {
+        from: crate::job::JobStatus,
+        to: crate::job::JobStatus,
+    },
+
+    #[error("Failed to read env file {}, {}", path, source)]
+    ReadEnvFile {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("Failed to parse env file {}, invalid line {}", path, line)]
+    ParseEnvFile { path: String, line: usize },
+
+    #[error("Invalid environment variable name '{}'", key)]
+    InvalidEnvKey { key: String },
+
+    #[error("Invalid webhook payload, {}", message)]
+    InvalidTrigger { message: String },
+
+    #[error("Project '{}' already has a job queued or running", name)]
+    ProjectBusy { name: String },
+
     #[error("Failed to execute database query, {}", source)]
     DatabaseQuery { source: rusqlite::Error },
 
@@ -50,6 +119,9 @@ impl actix_web::error::ResponseError for SubiloError {
         match &self {
             SubiloError::Authenticate { source: _ } => StatusCode::UNAUTHORIZED,
             SubiloError::MissingToken {} => StatusCode::UNAUTHORIZED,
+            SubiloError::UnknownKey { kid: _ } => StatusCode::UNAUTHORIZED,
+            SubiloError::InvalidTrigger { message: _ } => StatusCode::BAD_REQUEST,
+            SubiloError::ProjectBusy { name: _ } => StatusCode::CONFLICT,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
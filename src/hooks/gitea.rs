@@ -0,0 +1,80 @@
+use actix_web::{post, web, HttpRequest, HttpResponse, Result};
+use serde::Deserialize;
+
+use super::{header, Hook, Push};
+use crate::Context;
+
+#[derive(Debug, Deserialize)]
+struct PushEvent {
+    #[serde(rename = "ref")]
+    git_ref: String,
+    after: Option<String>,
+    repository: Repository,
+}
+
+#[derive(Debug, Deserialize)]
+struct Repository {
+    full_name: String,
+    html_url: Option<String>,
+    clone_url: Option<String>,
+    ssh_url: Option<String>,
+}
+
+/// Gitea and Forgejo push events, signed with `X-Gitea-Signature` or
+/// `X-Forgejo-Signature`
+#[post("/webhook/gitea")]
+pub async fn gitea_webhook(
+    req: HttpRequest,
+    body: web::Bytes,
+    ctx: web::Data<Context>,
+) -> Result<HttpResponse> {
+    let event = header(&req, "X-Gitea-Event")
+        .or_else(|| header(&req, "X-Forgejo-Event"))
+        .unwrap_or_default();
+    if event != "push" {
+        debug!("Ignoring Gitea event '{}'", event);
+        return Ok(HttpResponse::Accepted().body("Ignored"));
+    }
+
+    let signature =
+        match header(&req, "X-Gitea-Signature").or_else(|| header(&req, "X-Forgejo-Signature")) {
+            Some(signature) => signature.to_owned(),
+            None => return Ok(HttpResponse::Unauthorized().body("Unauthorized")),
+        };
+
+    let event: PushEvent = match serde_json::from_slice(&body) {
+        Ok(event) => event,
+        Err(err) => {
+            debug!("Failed to parse Gitea push event: {}", err);
+            return Ok(HttpResponse::BadRequest().body("Invalid push event"));
+        }
+    };
+
+    if super::is_deletion(event.after.as_deref()) {
+        debug!("Ignoring deletion of '{}'", &event.git_ref);
+        return Ok(HttpResponse::Accepted().body("Ignored"));
+    }
+
+    let repository = event.repository;
+    let push = Push {
+        repositories: vec![
+            Some(repository.full_name),
+            repository.html_url,
+            repository.clone_url,
+            repository.ssh_url,
+        ]
+        .into_iter()
+        .flatten()
+        .collect(),
+        git_ref: event.git_ref,
+        sha: event.after,
+    };
+
+    super::deploy(
+        push,
+        |project| project.gitea.as_ref(),
+        |hook: &Hook| super::verify_signature(&hook.secret, &body, &signature),
+        (*ctx.into_inner()).clone(),
+    )
+    .await
+}
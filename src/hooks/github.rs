@@ -0,0 +1,84 @@
+use actix_web::{post, web, HttpRequest, HttpResponse, Result};
+use serde::Deserialize;
+
+use super::{header, Hook, Push};
+use crate::Context;
+
+#[derive(Debug, Deserialize)]
+struct PushEvent {
+    #[serde(rename = "ref")]
+    git_ref: String,
+    after: Option<String>,
+    #[serde(default)]
+    deleted: bool,
+    repository: Repository,
+}
+
+#[derive(Debug, Deserialize)]
+struct Repository {
+    full_name: String,
+    html_url: Option<String>,
+    clone_url: Option<String>,
+    ssh_url: Option<String>,
+}
+
+/// GitHub push events, signed with `X-Hub-Signature-256`
+#[post("/webhook/github")]
+pub async fn github_webhook(
+    req: HttpRequest,
+    body: web::Bytes,
+    ctx: web::Data<Context>,
+) -> Result<HttpResponse> {
+    let event = header(&req, "X-GitHub-Event").unwrap_or_default();
+    if event == "ping" {
+        return Ok(HttpResponse::Ok().body("Pong"));
+    }
+
+    if event != "push" {
+        debug!("Ignoring GitHub event '{}'", event);
+        return Ok(HttpResponse::Accepted().body("Ignored"));
+    }
+
+    let signature = match header(&req, "X-Hub-Signature-256")
+        .and_then(|signature| signature.strip_prefix("sha256="))
+    {
+        Some(signature) => signature.to_owned(),
+        None => return Ok(HttpResponse::Unauthorized().body("Unauthorized")),
+    };
+
+    let event: PushEvent = match serde_json::from_slice(&body) {
+        Ok(event) => event,
+        Err(err) => {
+            debug!("Failed to parse GitHub push event: {}", err);
+            return Ok(HttpResponse::BadRequest().body("Invalid push event"));
+        }
+    };
+
+    if event.deleted {
+        debug!("Ignoring deletion of '{}'", &event.git_ref);
+        return Ok(HttpResponse::Accepted().body("Ignored"));
+    }
+
+    let repository = event.repository;
+    let push = Push {
+        repositories: vec![
+            Some(repository.full_name),
+            repository.html_url,
+            repository.clone_url,
+            repository.ssh_url,
+        ]
+        .into_iter()
+        .flatten()
+        .collect(),
+        git_ref: event.git_ref,
+        sha: event.after,
+    };
+
+    super::deploy(
+        push,
+        |project| project.github.as_ref(),
+        |hook: &Hook| super::verify_signature(&hook.secret, &body, &signature),
+        (*ctx.into_inner()).clone(),
+    )
+    .await
+}
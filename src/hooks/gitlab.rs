@@ -0,0 +1,76 @@
+use actix_web::{post, web, HttpRequest, HttpResponse, Result};
+use serde::Deserialize;
+
+use super::{header, Hook, Push};
+use crate::Context;
+
+#[derive(Debug, Deserialize)]
+struct PushEvent {
+    #[serde(rename = "ref")]
+    git_ref: String,
+    after: Option<String>,
+    project: Project,
+}
+
+#[derive(Debug, Deserialize)]
+struct Project {
+    path_with_namespace: String,
+    web_url: Option<String>,
+    git_http_url: Option<String>,
+    git_ssh_url: Option<String>,
+}
+
+/// GitLab push and tag push events, authenticated with `X-Gitlab-Token`
+#[post("/webhook/gitlab")]
+pub async fn gitlab_webhook(
+    req: HttpRequest,
+    body: web::Bytes,
+    ctx: web::Data<Context>,
+) -> Result<HttpResponse> {
+    let event = header(&req, "X-Gitlab-Event").unwrap_or_default();
+    if event != "Push Hook" && event != "Tag Push Hook" {
+        debug!("Ignoring GitLab event '{}'", event);
+        return Ok(HttpResponse::Accepted().body("Ignored"));
+    }
+
+    let token = match header(&req, "X-Gitlab-Token") {
+        Some(token) => token.to_owned(),
+        None => return Ok(HttpResponse::Unauthorized().body("Unauthorized")),
+    };
+
+    let event: PushEvent = match serde_json::from_slice(&body) {
+        Ok(event) => event,
+        Err(err) => {
+            debug!("Failed to parse GitLab push event: {}", err);
+            return Ok(HttpResponse::BadRequest().body("Invalid push event"));
+        }
+    };
+
+    if super::is_deletion(event.after.as_deref()) {
+        debug!("Ignoring deletion of '{}'", &event.git_ref);
+        return Ok(HttpResponse::Accepted().body("Ignored"));
+    }
+
+    let project = event.project;
+    let push = Push {
+        repositories: vec![
+            Some(project.path_with_namespace),
+            project.web_url,
+            project.git_http_url,
+            project.git_ssh_url,
+        ]
+        .into_iter()
+        .flatten()
+        .collect(),
+        git_ref: event.git_ref,
+        sha: event.after,
+    };
+
+    super::deploy(
+        push,
+        |project| project.gitlab.as_ref(),
+        |hook: &Hook| super::verify_token(&hook.secret, &token),
+        (*ctx.into_inner()).clone(),
+    )
+    .await
+}
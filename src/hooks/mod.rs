@@ -0,0 +1,220 @@
+use actix_web::error::ResponseError;
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::core::{self, Project};
+use crate::Context;
+
+pub mod gitea;
+pub mod github;
+pub mod gitlab;
+mod query;
+pub mod signed;
+
+/// Repository webhook of a project, configured per provider in `.subilorc`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Hook {
+    /// Repository as `owner/name` or any of its URLs
+    pub repository: String,
+    /// Branch patterns that trigger a deployment, any branch when both
+    /// `branches` and `tags` are empty
+    #[serde(default)]
+    pub branches: Vec<String>,
+    /// Tag patterns that trigger a deployment
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Shared secret used to verify the webhook
+    pub secret: String,
+}
+
+impl Hook {
+    fn matches_repository(&self, push: &Push) -> bool {
+        let repository = normalize_repository(&self.repository);
+        push.repositories
+            .iter()
+            .any(|candidate| normalize_repository(candidate) == repository)
+    }
+
+    fn matches_ref(&self, git_ref: &str) -> bool {
+        if let Some(branch) = git_ref.strip_prefix("refs/heads/") {
+            if self.branches.is_empty() && self.tags.is_empty() {
+                return true;
+            }
+            return matches_any(&self.branches, branch);
+        }
+
+        match git_ref.strip_prefix("refs/tags/") {
+            Some(tag) => matches_any(&self.tags, tag),
+            None => false,
+        }
+    }
+}
+
+/// A push event, common to every provider
+#[derive(Debug)]
+pub struct Push {
+    /// Names and URLs of the pushed repository
+    pub repositories: Vec<String>,
+    pub git_ref: String,
+    pub sha: Option<String>,
+}
+
+impl Push {
+    fn trigger(&self) -> core::Trigger {
+        core::Trigger {
+            git_ref: Some(self.git_ref.clone()),
+            sha: self.sha.clone(),
+            tag: self
+                .git_ref
+                .strip_prefix("refs/tags/")
+                .map(|tag| tag.to_owned()),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct DeployResponse {
+    names: Vec<String>,
+    /// Projects whose job could not be created
+    errors: Vec<DeployError>,
+}
+
+#[derive(Debug, Serialize)]
+struct DeployError {
+    project: String,
+    error: String,
+}
+
+/// Deploys the projects whose hook matches the push. Only the projects whose
+/// hook secret passes `verify` are considered, if none does the request is
+/// rejected as unauthorized, whether the repository is known or not.
+///
+/// A project failing to deploy does not prevent the others from being
+/// deployed, it is reported apart. The request fails only when every job
+/// failed to be created.
+pub async fn deploy<H, V>(
+    push: Push,
+    hook: H,
+    verify: V,
+    ctx: Context,
+) -> actix_web::Result<HttpResponse>
+where
+    H: Fn(&Project) -> Option<&Hook>,
+    V: Fn(&Hook) -> bool,
+{
+    // The signature is verified first, so that the response does not tell
+    // which repositories are deployed
+    let projects: Vec<Project> = ctx
+        .subilorc
+        .projects()
+        .projects
+        .iter()
+        .filter(|project| hook(project).is_some_and(&verify))
+        .filter(|project| hook(project).is_some_and(|hook| hook.matches_repository(&push)))
+        .cloned()
+        .collect();
+
+    if projects.is_empty() {
+        debug!(
+            "Webhook signature does not match the secret of any project of {:?}",
+            &push.repositories
+        );
+        return Ok(HttpResponse::Unauthorized().body("Unauthorized"));
+    }
+
+    let mut names = vec![];
+    let mut errors = vec![];
+    let mut error_status = None;
+    for project in projects {
+        if !hook(&project).is_some_and(|hook| hook.matches_ref(&push.git_ref)) {
+            debug!(
+                "Project '{}' does not deploy ref '{}'",
+                &project.name, &push.git_ref
+            );
+            continue;
+        }
+
+        let name = project.name.clone();
+        let deployment = core::Deployment {
+            project,
+            trigger: push.trigger(),
+            dry_run: false,
+        };
+
+        match core::spawn_job(deployment, ctx.clone()).await {
+            Ok(name) => names.push(name),
+            Err(err) => {
+                error!("Failed to deploy project '{}'. Error: {}", &name, err);
+                error_status = error_status.or_else(|| Some(err.status_code()));
+                errors.push(DeployError {
+                    project: name,
+                    error: err.to_string(),
+                });
+            }
+        }
+    }
+
+    let status = match error_status {
+        Some(status) if names.is_empty() => status,
+        _ => StatusCode::OK,
+    };
+
+    Ok(HttpResponse::build(status).json(DeployResponse { names, errors }))
+}
+
+/// Verifies a hex encoded HMAC-SHA256 signature of the body
+pub fn verify_signature(secret: &str, body: &[u8], signature: &str) -> bool {
+    let signature = match hex::decode(signature.trim()) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+
+    let mut mac = match Hmac::<Sha256>::new_from_slice(secret.as_bytes()) {
+        Ok(mac) => mac,
+        Err(_) => return false,
+    };
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Compares a token with the secret in constant time
+pub fn verify_token(secret: &str, token: &str) -> bool {
+    let (secret, token) = (secret.as_bytes(), token.as_bytes());
+    secret.len() == token.len()
+        && secret
+            .iter()
+            .zip(token)
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+/// Whether a push deleted the ref, its new commit is all zeros
+pub fn is_deletion(sha: Option<&str>) -> bool {
+    sha.is_some_and(|sha| !sha.is_empty() && sha.chars().all(|c| c == '0'))
+}
+
+pub fn header<'a>(req: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+}
+
+fn matches_any(patterns: &[String], name: &str) -> bool {
+    patterns.iter().any(|pattern| {
+        glob::Pattern::new(pattern)
+            .map(|pattern| pattern.matches(name))
+            .unwrap_or(false)
+    })
+}
+
+fn normalize_repository(repository: &str) -> String {
+    let repository = repository.trim().trim_end_matches('/').to_lowercase();
+    repository
+        .strip_suffix(".git")
+        .map(|repository| repository.to_owned())
+        .unwrap_or(repository)
+}
@@ -0,0 +1,9 @@
+pub const INSERT_NONCE: &str = "
+    INSERT OR IGNORE INTO nonces (project, nonce, created_at)
+    VALUES (?1, ?2, ?3)
+";
+
+pub const DELETE_EXPIRED_NONCES: &str = "
+    DELETE FROM nonces
+    WHERE created_at < ?1
+";
@@ -0,0 +1,127 @@
+use actix_web::error::ResponseError;
+use actix_web::{post, web, HttpRequest, HttpResponse, Result};
+use chrono::Utc;
+
+use super::{header, query};
+use crate::errors::SubiloError;
+use crate::{core, database, Context, WebhookPayload, WebhookResponse};
+
+/// Requests signed longer ago, or further in the future, than this many
+/// seconds are rejected. Nonces are kept for the same time.
+const MAX_TIMESTAMP_SKEW: i64 = 300;
+
+const MAX_NONCE_LENGTH: usize = 128;
+
+/// Same payload as `/webhook`, authenticated with the project's
+/// `webhook_secret` instead of a token. The `X-Subilo-Signature` header is
+/// the hex encoded HMAC-SHA256 of `<timestamp>.<nonce>.<body>`, with the
+/// values of the `X-Subilo-Timestamp` and `X-Subilo-Nonce` headers.
+#[post("/webhook/signed")]
+pub async fn signed_webhook(
+    req: HttpRequest,
+    body: web::Bytes,
+    ctx: web::Data<Context>,
+) -> Result<HttpResponse> {
+    let (timestamp, nonce, signature) = match (
+        header(&req, "X-Subilo-Timestamp"),
+        header(&req, "X-Subilo-Nonce"),
+        header(&req, "X-Subilo-Signature"),
+    ) {
+        (Some(timestamp), Some(nonce), Some(signature)) => (timestamp, nonce, signature),
+        _ => return Ok(HttpResponse::Unauthorized().body("Unauthorized")),
+    };
+
+    let payload: WebhookPayload = match serde_json::from_slice(&body) {
+        Ok(payload) => payload,
+        Err(err) => {
+            debug!("Failed to parse signed webhook payload: {}", err);
+            return Ok(HttpResponse::BadRequest().body("Invalid payload"));
+        }
+    };
+
+    // Unknown projects are not told apart from wrong signatures
+    let project = ctx.subilorc.projects().find(&payload.name).cloned();
+
+    let project = match project {
+        Some(project) => project,
+        None => return Ok(HttpResponse::Unauthorized().body("Unauthorized")),
+    };
+
+    let verified = project.webhook_secret.as_ref().is_some_and(|secret| {
+        let message = [timestamp.as_bytes(), b".", nonce.as_bytes(), b".", &body].concat();
+        super::verify_signature(secret, &message, signature)
+    });
+
+    if !verified {
+        debug!("Signature does not match project '{}'", &project.name);
+        return Ok(HttpResponse::Unauthorized().body("Unauthorized"));
+    }
+
+    let now = Utc::now().timestamp();
+    let fresh = timestamp
+        .parse::<i64>()
+        .is_ok_and(|timestamp| (now - timestamp).abs() <= MAX_TIMESTAMP_SKEW);
+
+    if !fresh {
+        debug!("Rejecting stale timestamp '{}'", timestamp);
+        return Ok(HttpResponse::Unauthorized().body("Stale timestamp"));
+    }
+
+    if nonce.is_empty() || nonce.len() > MAX_NONCE_LENGTH {
+        return Ok(HttpResponse::Unauthorized().body("Invalid nonce"));
+    }
+
+    if !use_nonce(&ctx, &project.name, nonce, now).await? {
+        debug!("Rejecting reused nonce '{}'", nonce);
+        return Ok(HttpResponse::Unauthorized().body("Nonce already used"));
+    }
+
+    if let Err(err) = payload.trigger.validate(&project) {
+        return Ok(err.error_response());
+    }
+
+    let deployment = core::Deployment {
+        project,
+        trigger: payload.trigger,
+        dry_run: payload.dry_run,
+    };
+
+    let context = (*ctx.into_inner()).clone();
+    match core::spawn_job(deployment, context).await {
+        Ok(job_id) => Ok(HttpResponse::Ok().json(WebhookResponse { name: job_id })),
+        Err(err) => Ok(err.error_response()),
+    }
+}
+
+/// Records the nonce of a project, returns false when it was already used
+async fn use_nonce(
+    ctx: &Context,
+    project: &str,
+    nonce: &str,
+    now: i64,
+) -> Result<bool, SubiloError> {
+    let expired = database::Execute {
+        query: query::DELETE_EXPIRED_NONCES.to_owned(),
+        params: vec![(now - MAX_TIMESTAMP_SKEW * 2).to_string()],
+    };
+
+    ctx.database
+        .send(expired)
+        .await
+        .map_err(|err| SubiloError::DatabaseActor { source: err })?
+        .map_err(|err| SubiloError::DatabaseQuery { source: err })?;
+
+    let insert = database::Execute {
+        query: query::INSERT_NONCE.to_owned(),
+        params: vec![project.to_owned(), nonce.to_owned(), now.to_string()],
+    };
+
+    let inserted = ctx
+        .database
+        .send(insert)
+        .await
+        .map_err(|err| SubiloError::DatabaseActor { source: err })?
+        .map_err(|err| SubiloError::DatabaseQuery { source: err })?;
+
+    Ok(inserted > 0)
+}
@@ -3,6 +3,8 @@ use nanoid::nanoid;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::io::Write;
+use std::sync::Arc;
+use std::time::Instant;
 
 use crate::core;
 use crate::database;
@@ -10,17 +12,66 @@ use crate::Context;
 use crate::SubiloError;
 
 pub mod query;
+mod redact;
+mod registry;
+mod retention;
+mod tail;
 
-#[derive(Debug, Deserialize, Serialize)]
+pub use redact::{LogWriter, Redactor};
+pub use registry::{Handle, Registry};
+pub use retention::schedule_pruning;
+pub use tail::LogTail;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum JobStatus {
-    Started,
+    Queued,
+    Running,
     Succeeded,
     Failed,
+    Cancelled,
+    TimedOut,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Succeeded => "succeeded",
+            JobStatus::Failed => "failed",
+            JobStatus::Cancelled => "cancelled",
+            JobStatus::TimedOut => "timed-out",
+        }
+    }
+
+    pub fn is_final(&self) -> bool { !matches!(self, JobStatus::Queued | JobStatus::Running) }
+
+    /// Validates a status change. Jobs move from `queued` to `running` and end
+    /// in exactly one final status; a `queued` job can only end as `cancelled`.
+    pub fn transition(self, next: JobStatus) -> Result<JobStatus, SubiloError> {
+        let allowed = match (self, next) {
+            (JobStatus::Queued, JobStatus::Running) => true,
+            (JobStatus::Queued, JobStatus::Cancelled) => true,
+            (JobStatus::Running, next) => next.is_final(),
+            _ => false,
+        };
+
+        if allowed {
+            Ok(next)
+        } else {
+            Err(SubiloError::JobTransition {
+                from: self,
+                to: next,
+            })
+        }
+    }
 }
 
 impl std::fmt::Display for JobStatus {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result { write!(f, "{:?}", self) }
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -29,8 +80,39 @@ pub struct PartialJob {
     pub name: String,
     pub status: String,
     pub project: String,
-    pub started_at: String,
-    pub ended_at: String,
+    /// When the job was created, before waiting in the queue
+    pub queued_at: String,
+    /// When the job left the queue, `None` while it waits or if it was
+    /// cancelled before starting
+    pub started_at: Option<String>,
+    pub ended_at: Option<String>,
+    pub duration_ms: Option<i64>,
+    /// Whether the commands were only shown, not run
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Order {
+    Asc,
+    #[default]
+    Desc,
+}
+
+/// Filter and page of `GET /jobs`, jobs are sorted by `queued_at`, which does
+/// not change once the job is created
+#[derive(Debug)]
+pub struct JobsFilter {
+    pub project: Option<String>,
+    /// Glob patterns the project has to match, any project when `None`
+    pub projects: Option<Vec<String>>,
+    pub status: Option<JobStatus>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    /// Id of the last job of the previous page
+    pub cursor: Option<String>,
+    pub limit: u32,
+    pub order: Order,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -39,38 +121,62 @@ pub struct Job {
     pub name: String,
     pub status: String,
     pub project: String,
-    pub started_at: String,
-    pub ended_at: String,
+    pub queued_at: String,
+    pub started_at: Option<String>,
+    pub ended_at: Option<String>,
+    pub duration_ms: Option<i64>,
+    pub dry_run: bool,
     pub commands: serde_json::Value,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Step {
+    pub index: i64,
+    pub command: String,
+    pub exit_code: Option<i32>,
+    pub started_at: String,
+    pub ended_at: Option<String>,
+    pub duration_ms: Option<i64>,
+}
+
+struct CurrentStep {
+    index: usize,
+    started: Instant,
 }
 
 pub struct Witness {
     id: String,
+    name: String,
     log: std::fs::File,
+    redactor: Redactor,
     context: Context,
+    handle: Arc<Handle>,
+    status: JobStatus,
+    steps: usize,
+    current_step: Option<CurrentStep>,
 }
 
 impl Witness {
-    pub async fn new(
-        job_name: String,
-        project: core::Project,
-        context: Context,
-    ) -> Result<Self, SubiloError> {
+    pub async fn new(deployment: &core::Deployment, context: Context) -> Result<Self, SubiloError> {
         fs::create_dir_all(&context.logs_dir)
             .map_err(|err| SubiloError::CreateLogDir { source: err })?;
 
-        let mut log = fs::File::create(create_log_name(&job_name, &context.logs_dir))
-            .map_err(|err| SubiloError::CreateLogFile { source: err })?;
-
-        log.write_all(project.description().as_bytes())
-            .map_err(|err| SubiloError::WriteLogFile { source: err })?;
-
+        let project = &deployment.project;
         let id = nanoid!();
-        let status = JobStatus::Started.to_string().to_lowercase();
-        let started_at = now();
+        let job_name = core::create_job_name(&project.name, &id);
+        let status = JobStatus::Queued;
+        let queued_at = now();
         let project_name = project.name.clone();
+
+        // Secrets written in the commands are not stored. An environment that
+        // can not be read is reported once the job runs
+        let redactor = match deployment.environment(&id, &job_name) {
+            Ok(env) => Redactor::new(env.secrets),
+            Err(_) => Redactor::default(),
+        };
         let commands = project
-            .commands_to_json()
+            .commands_to_json(&redactor)
             .map_err(|err| SubiloError::ParseProjectCommands { source: err })?;
 
         context
@@ -79,90 +185,218 @@ impl Witness {
                 query: query::INSERT_JOB.to_owned(),
                 params: vec![
                     id.clone(),
-                    job_name,
-                    status,
+                    job_name.clone(),
+                    status.to_string(),
                     project_name,
                     commands,
-                    started_at,
+                    queued_at,
+                    (deployment.dry_run as i32).to_string(),
                 ],
             })
             .await
             .map_err(|err| SubiloError::DatabaseActor { source: err })?
             .map_err(|err| SubiloError::DatabaseQuery { source: err })?;
 
-        Ok(Self { id, context, log })
-    }
+        // Created once the job is stored, so the log of another job with the
+        // same name is never truncated
+        let mut log = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(create_log_name(&job_name, &context.logs_dir))
+            .map_err(|err| SubiloError::CreateLogFile { source: err })?;
 
-    pub fn report_command(&mut self, command: &str) -> Result<(), SubiloError> {
-        self.log
-            .write_all(format!("$ {}\n", &command).as_bytes())
-            .map_err(|err| SubiloError::WriteLogFile { source: err })
+        log.write_all(project.description().as_bytes())
+            .map_err(|err| SubiloError::WriteLogFile { source: err })?;
+
+        let handle = context.jobs.register(&id);
+
+        Ok(Self {
+            id,
+            name: job_name,
+            context,
+            handle,
+            log,
+            redactor,
+            status,
+            steps: 0,
+            current_step: None,
+        })
     }
 
-    pub fn report_command_success(&self) -> Result<(), SubiloError> {
-        let ended_at = now();
-        let status = JobStatus::Succeeded.to_string().to_lowercase();
+    pub fn report_command(&mut self, command: &str) -> Result<(), SubiloError> {
+        self.write_log(&format!("$ {}\n", &command))?;
 
-        let update_job = self.context.database.send(database::Execute {
-            query: query::UPDATE_JOB.to_owned(),
-            params: vec![self.id.clone(), status, ended_at],
+        let index = self.steps;
+        self.steps += 1;
+        self.current_step = Some(CurrentStep {
+            index,
+            started: Instant::now(),
         });
 
-        block_on(update_job)
-            .map_err(|err| SubiloError::DatabaseActor { source: err })?
-            .map_err(|err| SubiloError::DatabaseQuery { source: err })
-            .map(|_res| ())
+        self.execute(
+            query::INSERT_JOB_STEP,
+            vec![
+                self.id.clone(),
+                index.to_string(),
+                self.redactor.redact_str(command),
+                now(),
+            ],
+        )
     }
 
+    /// Writes what the job would run, for dry runs
+    pub fn report_plan(&mut self, plan: &str) -> Result<(), SubiloError> { self.write_log(plan) }
+
+    pub fn report_command_success(&mut self) -> Result<(), SubiloError> { self.end_step(Some(0)) }
+
     pub fn report_command_error_by_code(
         &mut self,
         status_code: Option<i32>,
     ) -> Result<(), SubiloError> {
         match status_code {
-            Some(code) => self
-                .log
-                .write_all(format!("Exit {}\n", code).as_bytes())
-                .map_err(|err| SubiloError::WriteLogFile { source: err })?,
-            None => self
-                .log
-                .write_all("Process terminated by signal\n".to_string().as_bytes())
-                .map_err(|err| SubiloError::WriteLogFile { source: err })?,
+            Some(code) => self.write_log(&format!("Exit {}\n", code))?,
+            None => self.write_log("Process terminated by signal\n")?,
+        };
+
+        self.end_step(status_code)
+    }
+
+    pub fn report_command_error(&mut self, err: core::RunError) -> Result<(), SubiloError> {
+        self.write_log(&err.to_string())?;
+
+        self.end_step(None)
+    }
+
+    /// Reports an error that prevents the job from running its commands
+    pub fn report_error(&mut self, err: &SubiloError) -> Result<(), SubiloError> {
+        self.write_log(&format!("[FATAL] {}\n", err))
+    }
+
+    pub fn report_command_cancelled(&mut self) -> Result<(), SubiloError> {
+        self.write_log("Job cancelled\n")?;
+
+        self.end_step(None)
+    }
+
+    pub fn report_command_timed_out(&mut self, message: &str) -> Result<(), SubiloError> {
+        self.write_log(&format!("{}\n", message))?;
+
+        self.end_step(None)
+    }
+
+    /// Marks the job as running, right before its first command is executed.
+    pub fn report_start(&mut self) -> Result<(), SubiloError> {
+        self.status = self.status.transition(JobStatus::Running)?;
+
+        self.execute(
+            query::UPDATE_JOB_START,
+            vec![self.id.clone(), self.status.to_string(), now()],
+        )
+    }
+
+    /// Writes the final status of the job. Called once, after the last command.
+    pub fn report_end(&mut self, status: JobStatus) -> Result<(), SubiloError> {
+        self.status = self.status.transition(status)?;
+
+        self.execute(
+            query::UPDATE_JOB,
+            vec![self.id.clone(), self.status.to_string(), now()],
+        )
+    }
+
+    /// Secrets masked from everything written to the log from now on
+    pub fn set_redactor(&mut self, redactor: Redactor) { self.redactor = redactor }
+
+    pub fn log_writer(&self) -> Result<LogWriter, std::io::Error> {
+        let log = self.log.try_clone()?;
+        Ok(LogWriter::new(log, self.redactor.clone()))
+    }
+
+    pub fn is_cancelled(&self) -> bool { self.handle.is_cancelled() }
+
+    pub fn id(&self) -> &str { &self.id }
+
+    pub fn name(&self) -> &str { &self.name }
+
+    pub fn handle(&self) -> Arc<Handle> { self.handle.clone() }
+
+    fn write_log(&mut self, text: &str) -> Result<(), SubiloError> {
+        self.log
+            .write_all(&self.redactor.redact(text.as_bytes()))
+            .map_err(|err| SubiloError::WriteLogFile { source: err })
+    }
+
+    fn end_step(&mut self, exit_code: Option<i32>) -> Result<(), SubiloError> {
+        let step = match self.current_step.take() {
+            Some(step) => step,
+            None => return Ok(()),
         };
 
-        let ended_at = now();
-        let status = JobStatus::Failed.to_string().to_lowercase();
+        let exit_code = exit_code.map(|code| code.to_string()).unwrap_or_default();
+        let duration_ms = step.started.elapsed().as_millis().to_string();
+
+        self.execute(
+            query::UPDATE_JOB_STEP,
+            vec![
+                self.id.clone(),
+                step.index.to_string(),
+                exit_code,
+                now(),
+                duration_ms,
+            ],
+        )
+    }
 
-        let update_job = self.context.database.send(database::Execute {
-            query: query::UPDATE_JOB.to_owned(),
-            params: vec![self.id.clone(), status, ended_at],
+    fn execute(&self, query: &str, params: Vec<String>) -> Result<(), SubiloError> {
+        let execute = self.context.database.send(database::Execute {
+            query: query.to_owned(),
+            params,
         });
 
-        block_on(update_job)
+        block_on(execute)
             .map_err(|err| SubiloError::DatabaseActor { source: err })?
             .map_err(|err| SubiloError::DatabaseQuery { source: err })
             .map(|_res| ())
     }
+}
 
-    pub fn report_command_error(&mut self, err: core::RunError) -> Result<(), SubiloError> {
-        self.log
-            .write_all(err.to_string().as_bytes())
-            .map_err(|err| SubiloError::WriteLogFile { source: err })?;
+impl Drop for Witness {
+    fn drop(&mut self) { self.context.jobs.remove(&self.id) }
+}
 
-        let ended_at = now();
-        let status = JobStatus::Failed.to_string().to_lowercase();
+/// Marks the jobs a previous run of the agent left queued or running as
+/// failed, as nothing will run them anymore
+pub async fn fail_interrupted(context: &Context) -> Result<usize, SubiloError> {
+    let mut failed = 0;
 
-        let update_job = self.context.database.send(database::Execute {
-            query: query::UPDATE_JOB.to_owned(),
-            params: vec![self.id.clone(), status, ended_at],
-        });
+    for statement in [
+        query::END_INTERRUPTED_JOB_STEPS,
+        query::END_INTERRUPTED_JOBS,
+    ]
+    .iter()
+    {
+        let execute = database::Execute {
+            query: statement.to_string(),
+            params: vec![now()],
+        };
 
-        block_on(update_job)
+        failed = context
+            .database
+            .send(execute)
+            .await
             .map_err(|err| SubiloError::DatabaseActor { source: err })?
-            .map_err(|err| SubiloError::DatabaseQuery { source: err })
-            .map(|_res| ())
+            .map_err(|err| SubiloError::DatabaseQuery { source: err })?;
     }
 
-    pub fn try_clone_log(&self) -> Result<std::fs::File, std::io::Error> { self.log.try_clone() }
+    Ok(failed)
+}
+
+/// Milliseconds between two stored dates, `None` while the job has not ended
+/// or if it never started
+pub fn duration_ms(started_at: Option<&str>, ended_at: Option<&str>) -> Option<i64> {
+    let started_at = chrono::DateTime::parse_from_rfc3339(started_at?).ok()?;
+    let ended_at = chrono::DateTime::parse_from_rfc3339(ended_at?).ok()?;
+    Some((ended_at - started_at).num_milliseconds())
 }
 
 fn now() -> String { chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true) }
@@ -1,6 +1,9 @@
+use super::{JobsFilter, Order};
+
+/// Jobs are created queued, `started_at` is set when the job starts
 pub const INSERT_JOB: &str = "
-    INSERT INTO jobs (id, name, status, project, commands, started_at)
-    VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+    INSERT INTO jobs (id, name, status, project, commands, queued_at, dry_run)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
 ";
 
 pub const UPDATE_JOB: &str = "
@@ -9,13 +12,140 @@ pub const UPDATE_JOB: &str = "
     WHERE id = ?1
 ";
 
-pub const GET_ALL_JOBS: &str = "
-    SELECT id, name, status, project, started_at, ended_at
-    FROM jobs
+pub const UPDATE_JOB_START: &str = "
+    UPDATE jobs
+    SET status = ?2, started_at = ?3
+    WHERE id = ?1
 ";
 
+/// Builds the query to list jobs matching the filter, fetching one row more
+/// than the limit to know whether there is a next page. The cursor has to be
+/// the id of an existing job, otherwise no job is listed.
+pub fn get_jobs(filter: &JobsFilter) -> (String, Vec<String>) {
+    let mut conditions = vec![];
+    let mut params = vec![];
+
+    let mut condition = |condition: &str, param: String| {
+        params.push(param);
+        conditions.push(condition.replace("?", &format!("?{}", params.len())));
+    };
+
+    if let Some(project) = &filter.project {
+        condition("project = ?", project.clone());
+    }
+    if let Some(status) = &filter.status {
+        condition("status = ?", status.to_string());
+    }
+    if let Some(since) = &filter.since {
+        condition("queued_at >= ?", since.clone());
+    }
+    if let Some(until) = &filter.until {
+        condition("queued_at <= ?", until.clone());
+    }
+
+    let (direction, operator) = match filter.order {
+        Order::Desc => ("DESC", "<"),
+        Order::Asc => ("ASC", ">"),
+    };
+
+    if let Some(cursor) = &filter.cursor {
+        let cursor_condition = format!(
+            "(queued_at {operator} (SELECT queued_at FROM jobs WHERE id = ?) OR \
+             (queued_at = (SELECT queued_at FROM jobs WHERE id = ?) AND id {operator} ?))",
+            operator = operator
+        );
+        condition(&cursor_condition, cursor.clone());
+    }
+
+    if let Some(patterns) = &filter.projects {
+        let mut alternatives = vec![];
+        for pattern in patterns {
+            params.push(pattern.clone());
+            alternatives.push(format!("project GLOB ?{}", params.len()));
+        }
+
+        if alternatives.is_empty() {
+            conditions.push("0".to_owned());
+        } else {
+            conditions.push(format!("({})", alternatives.join(" OR ")));
+        }
+    }
+
+    let mut query = "
+    SELECT id, name, status, project, started_at, ended_at, dry_run, queued_at
+    FROM jobs"
+        .to_owned();
+
+    if !conditions.is_empty() {
+        query.push_str("\n    WHERE ");
+        query.push_str(&conditions.join("\n    AND "));
+    }
+
+    query.push_str(&format!(
+        "\n    ORDER BY queued_at {direction}, id {direction}\n    LIMIT {limit}\n",
+        direction = direction,
+        limit = filter.limit + 1
+    ));
+
+    (query, params)
+}
+
 pub const GET_JOB_BY_ID: &str = "
-    SELECT id, name, status, project, commands, started_at, ended_at
+    SELECT id, name, status, project, commands, started_at, ended_at, dry_run, queued_at
     FROM jobs
     WHERE id = ?1
 ";
+
+pub const INSERT_JOB_STEP: &str = "
+    INSERT INTO job_steps (job_id, position, command, started_at)
+    VALUES (?1, ?2, ?3, ?4)
+";
+
+pub const UPDATE_JOB_STEP: &str = "
+    UPDATE job_steps
+    SET exit_code = NULLIF(?3, ''), ended_at = ?4, duration_ms = ?5
+    WHERE job_id = ?1 AND position = ?2
+";
+
+pub const GET_JOB_STEPS: &str = "
+    SELECT position, command, exit_code, started_at, ended_at, duration_ms
+    FROM job_steps
+    WHERE job_id = ?1
+    ORDER BY position
+";
+
+pub const GET_JOB_STATUS: &str = "
+    SELECT status
+    FROM jobs
+    WHERE id = ?1
+";
+
+pub const GET_EXPIRED_JOBS: &str = "
+    SELECT name
+    FROM jobs
+    WHERE ended_at IS NOT NULL AND ended_at < ?1
+";
+
+pub const DELETE_EXPIRED_JOB_STEPS: &str = "
+    DELETE FROM job_steps
+    WHERE job_id IN (SELECT id FROM jobs WHERE ended_at IS NOT NULL AND ended_at < ?1)
+";
+
+pub const DELETE_EXPIRED_JOBS: &str = "
+    DELETE FROM jobs
+    WHERE ended_at IS NOT NULL AND ended_at < ?1
+";
+
+/// Jobs left queued or running when the agent stopped
+pub const END_INTERRUPTED_JOB_STEPS: &str = "
+    UPDATE job_steps
+    SET ended_at = ?1
+    WHERE ended_at IS NULL
+      AND job_id IN (SELECT id FROM jobs WHERE status IN ('queued', 'running'))
+";
+
+pub const END_INTERRUPTED_JOBS: &str = "
+    UPDATE jobs
+    SET status = 'failed', ended_at = ?1
+    WHERE status IN ('queued', 'running')
+";
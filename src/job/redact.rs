@@ -0,0 +1,218 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+const MASK: &[u8] = b"********";
+
+/// Values shorter than this are not masked, they would hide too much of the log
+const MIN_SECRET_LENGTH: usize = 4;
+
+/// Output is written to the log once a line ends or this many bytes are read
+const MAX_PENDING_BYTES: usize = 64 * 1024;
+
+/// Masks secret values before they are written to a job log.
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    secrets: Arc<Vec<Vec<u8>>>,
+}
+
+impl Redactor {
+    pub fn new<I: IntoIterator<Item = String>>(values: I) -> Self {
+        // Output is masked line by line, so are multi-line values
+        let mut secrets: Vec<Vec<u8>> = values
+            .into_iter()
+            .flat_map(|value| {
+                value
+                    .lines()
+                    .map(|line| line.trim().as_bytes().to_vec())
+                    .collect::<Vec<_>>()
+            })
+            .filter(|secret| secret.len() >= MIN_SECRET_LENGTH)
+            .collect();
+
+        // Longest first, so a secret containing another one is fully masked
+        secrets.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+        secrets.dedup();
+
+        Self {
+            secrets: Arc::new(secrets),
+        }
+    }
+
+    pub fn redact(&self, text: &[u8]) -> Vec<u8> { self.redact_until(text, text.len()).0 }
+
+    pub fn redact_str(&self, text: &str) -> String {
+        String::from_utf8_lossy(&self.redact(text.as_bytes())).into_owned()
+    }
+
+    /// Length of the longest secret
+    fn max_length(&self) -> usize { self.secrets.first().map_or(0, |secret| secret.len()) }
+
+    /// Redacts the text up to `end`, including the secrets that start before
+    /// `end` and end after it. Returns the redacted text and how much of the
+    /// text it covers.
+    fn redact_until(&self, text: &[u8], end: usize) -> (Vec<u8>, usize) {
+        if self.secrets.is_empty() {
+            return (text[..end].to_vec(), end);
+        }
+
+        let mut redacted = Vec::with_capacity(end);
+        let mut position = 0;
+
+        'text: while position < end {
+            for secret in self.secrets.iter() {
+                if text[position..].starts_with(secret) {
+                    redacted.extend_from_slice(MASK);
+                    position += secret.len();
+                    continue 'text;
+                }
+            }
+
+            redacted.push(text[position]);
+            position += 1;
+        }
+
+        (redacted, position)
+    }
+}
+
+/// Writes command output to the job log through a `Redactor`
+pub struct LogWriter {
+    log: File,
+    redactor: Redactor,
+}
+
+impl LogWriter {
+    pub fn new(log: File, redactor: Redactor) -> Self { Self { log, redactor } }
+
+    /// Copies everything from the reader, masking secrets on complete lines
+    pub fn copy_from<R: Read>(&mut self, mut reader: R) -> io::Result<()> {
+        let mut buffer = [0; 8 * 1024];
+        let mut pending = vec![];
+
+        loop {
+            let read = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            pending.extend_from_slice(&buffer[..read]);
+
+            let line_end = pending
+                .iter()
+                .rposition(|byte| *byte == b'\n' || *byte == b'\r');
+            let end = match line_end {
+                Some(position) => position + 1,
+                // Keeps enough of the output to mask a secret that was only
+                // partially read
+                None if pending.len() >= MAX_PENDING_BYTES => {
+                    let kept = self.redactor.max_length().saturating_sub(1);
+                    pending.len().saturating_sub(kept)
+                }
+                None => continue,
+            };
+
+            let (redacted, written) = self.redactor.redact_until(&pending, end);
+            self.log.write_all(&redacted)?;
+            pending.drain(..written);
+        }
+
+        self.log.write_all(&self.redactor.redact(&pending))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    /// Reads the given chunks one by one, like a pipe would
+    struct Chunks(Vec<Vec<u8>>);
+
+    impl Read for Chunks {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() {
+                return Ok(0);
+            }
+            let chunk = &mut self.0[0];
+            let read = chunk.len().min(buffer.len());
+            buffer[..read].copy_from_slice(&chunk[..read]);
+            chunk.drain(..read);
+            if chunk.is_empty() {
+                self.0.remove(0);
+            }
+            Ok(read)
+        }
+    }
+
+    fn copy(name: &str, redactor: Redactor, chunks: Vec<&[u8]>) -> String {
+        let chunks = Chunks(chunks.into_iter().map(|chunk| chunk.to_vec()).collect());
+        fs::create_dir_all("test/redact").unwrap();
+        let path = format!("test/redact/{}.log", name);
+        let mut writer = LogWriter::new(File::create(&path).unwrap(), redactor);
+        writer.copy_from(chunks).unwrap();
+        fs::read_to_string(&path).unwrap()
+    }
+
+    #[test]
+    fn test_redact() {
+        let redactor = Redactor::new(vec!["super-secret".to_owned(), "secret".to_owned()]);
+        assert_eq!(
+            redactor.redact_str("token=super-secret, other=secret."),
+            "token=********, other=********."
+        );
+        assert_eq!(redactor.redact_str("nothing to hide"), "nothing to hide");
+
+        let redactor = Redactor::default();
+        assert_eq!(redactor.redact_str("super-secret"), "super-secret");
+    }
+
+    #[test]
+    fn test_redact_short_values() {
+        let redactor = Redactor::new(vec!["abc".to_owned(), "".to_owned(), "abcd".to_owned()]);
+        assert_eq!(redactor.redact_str("abc abcd"), "abc ********");
+    }
+
+    #[test]
+    fn test_redact_multiline_values() {
+        let key = "-----BEGIN KEY-----\nMIIBOgIBAAJBAK\n-----END KEY-----\n";
+        let redactor = Redactor::new(vec![key.to_owned()]);
+        assert_eq!(redactor.redact_str(key), "********\n********\n********\n");
+    }
+
+    #[test]
+    fn test_log_writer() {
+        let redactor = Redactor::new(vec!["super-secret".to_owned()]);
+
+        // A secret split across reads
+        let log = copy(
+            "split-reads",
+            redactor.clone(),
+            vec![b"token=super-", b"secret\nnext ", b"line\n"],
+        );
+        assert_eq!(log, "token=********\nnext line\n");
+
+        // Output without a final line end
+        let log = copy("no-line-end", redactor.clone(), vec![b"super", b"-secret"]);
+        assert_eq!(log, "********");
+
+        // A secret split across lines is not one
+        let log = copy("split-lines", redactor, vec![b"super-\nsecret\n"]);
+        assert_eq!(log, "super-\nsecret\n");
+    }
+
+    #[test]
+    fn test_log_writer_long_lines() {
+        let redactor = Redactor::new(vec!["super-secret".to_owned()]);
+
+        // The log is written before the line ends, in the middle of the secret
+        let mut output = vec![b'a'; MAX_PENDING_BYTES - 4];
+        output.extend_from_slice(b"super-secret\n");
+        let log = copy("long-line", redactor, vec![&output]);
+
+        let mut expected = "a".repeat(MAX_PENDING_BYTES - 4);
+        expected.push_str("********\n");
+        assert_eq!(log, expected);
+    }
+}
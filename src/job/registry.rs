@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Shared state of a job that has not finished yet, used to signal it from
+/// request handlers while its commands run on the job thread.
+#[derive(Debug, Default)]
+pub struct Handle {
+    cancelled: AtomicBool,
+}
+
+impl Handle {
+    pub fn cancel(&self) { self.cancelled.store(true, Ordering::SeqCst) }
+
+    pub fn is_cancelled(&self) -> bool { self.cancelled.load(Ordering::SeqCst) }
+}
+
+/// Handles of the jobs that have not finished yet, by job id.
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    handles: Arc<Mutex<HashMap<String, Arc<Handle>>>>,
+}
+
+impl Registry {
+    pub fn register(&self, id: &str) -> Arc<Handle> {
+        let handle = Arc::new(Handle::default());
+        self.handles
+            .lock()
+            .expect("Failed to lock jobs registry")
+            .insert(id.to_owned(), handle.clone());
+        handle
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<Handle>> {
+        self.handles
+            .lock()
+            .expect("Failed to lock jobs registry")
+            .get(id)
+            .cloned()
+    }
+
+    pub fn remove(&self, id: &str) {
+        self.handles
+            .lock()
+            .expect("Failed to lock jobs registry")
+            .remove(id);
+    }
+}
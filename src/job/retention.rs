@@ -0,0 +1,66 @@
+use actix_rt::time::delay_for;
+use std::time::Duration;
+
+use super::query;
+use crate::database;
+use crate::Context;
+use crate::SubiloError;
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Deletes the jobs that ended more than `days` ago, with their steps and
+/// logs, now and then every hour.
+pub fn schedule_pruning(context: Context, days: u32) {
+    actix_rt::spawn(async move {
+        loop {
+            match prune(&context, days).await {
+                Ok(0) => {}
+                Ok(pruned) => info!("Deleted {} jobs older than {} days", pruned, days),
+                Err(err) => error!("Failed to delete old jobs. Error: {}", err),
+            }
+
+            delay_for(PRUNE_INTERVAL).await;
+        }
+    });
+}
+
+async fn prune(context: &Context, days: u32) -> Result<usize, SubiloError> {
+    let cutoff = (chrono::Utc::now() - chrono::Duration::days(days.into()))
+        .to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+
+    let query = database::Query {
+        query: query::GET_EXPIRED_JOBS.to_owned(),
+        params: vec![cutoff.clone()],
+        map_result: |row| row.get::<_, String>(0),
+    };
+
+    let names = context
+        .database
+        .send(query)
+        .await
+        .map_err(|err| SubiloError::DatabaseActor { source: err })?
+        .map_err(|err| SubiloError::DatabaseQuery { source: err })?;
+
+    for name in &names {
+        let log_name = super::create_log_name(name, &context.logs_dir);
+        if let Err(err) = tokio::fs::remove_file(&log_name).await {
+            debug!("Failed to delete log '{}'. Error: {}", log_name, err);
+        }
+    }
+
+    for statement in [query::DELETE_EXPIRED_JOB_STEPS, query::DELETE_EXPIRED_JOBS].iter() {
+        let execute = database::Execute {
+            query: statement.to_string(),
+            params: vec![cutoff.clone()],
+        };
+
+        context
+            .database
+            .send(execute)
+            .await
+            .map_err(|err| SubiloError::DatabaseActor { source: err })?
+            .map_err(|err| SubiloError::DatabaseQuery { source: err })?;
+    }
+
+    Ok(names.len())
+}
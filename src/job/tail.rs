@@ -0,0 +1,116 @@
+use actix_rt::time::delay_for;
+use actix_web::web::Bytes;
+use serde_json::json;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+
+use super::query;
+use crate::database;
+use crate::Context;
+use crate::SubiloError;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Follows a job log file and turns it into Server-Sent Events: the content
+/// written so far, then new lines as they are written and a final `status`
+/// event once the job has ended.
+pub struct LogTail {
+    id: String,
+    file: tokio::fs::File,
+    pending: Vec<u8>,
+    context: Context,
+    closed: bool,
+}
+
+impl LogTail {
+    pub async fn open(id: String, log_name: String, context: Context) -> Result<Self, SubiloError> {
+        let file = tokio::fs::File::open(log_name)
+            .await
+            .map_err(|err| SubiloError::ReadLogFile { source: err })?;
+
+        Ok(Self {
+            id,
+            file,
+            pending: vec![],
+            context,
+            closed: false,
+        })
+    }
+
+    pub async fn next_event(&mut self) -> Result<Option<Bytes>, SubiloError> {
+        if self.closed {
+            return Ok(None);
+        }
+
+        loop {
+            // Checked before reading so the last read happens after the job ended
+            let running = self.context.jobs.get(&self.id).is_some();
+
+            self.file
+                .read_to_end(&mut self.pending)
+                .await
+                .map_err(|err| SubiloError::ReadLogFile { source: err })?;
+
+            // While the job runs only complete lines are sent
+            let end = if running {
+                self.pending
+                    .iter()
+                    .rposition(|byte| *byte == b'\n')
+                    .map_or(0, |position| position + 1)
+            } else {
+                self.pending.len()
+            };
+
+            if end > 0 {
+                let text: Vec<u8> = self.pending.drain(..end).collect();
+                return Ok(Some(data_event(&String::from_utf8_lossy(&text))));
+            }
+
+            if !running {
+                self.closed = true;
+                let status = self.status().await?;
+                return Ok(Some(status_event(status)));
+            }
+
+            delay_for(POLL_INTERVAL).await;
+        }
+    }
+
+    async fn status(&self) -> Result<Option<String>, SubiloError> {
+        let query = database::Query {
+            query: query::GET_JOB_STATUS.to_owned(),
+            params: vec![self.id.clone()],
+            map_result: |row| row.get::<_, String>(0),
+        };
+
+        let statuses = self
+            .context
+            .database
+            .send(query)
+            .await
+            .map_err(|err| SubiloError::DatabaseActor { source: err })?
+            .map_err(|err| SubiloError::DatabaseQuery { source: err })?;
+
+        Ok(statuses.into_iter().next())
+    }
+}
+
+fn data_event(text: &str) -> Bytes {
+    let text = text.replace("\r\n", "\n");
+    let text = text.strip_suffix('\n').unwrap_or(&text);
+
+    let mut event = String::new();
+    for line in text.split(['\n', '\r']) {
+        event.push_str("data: ");
+        event.push_str(line);
+        event.push('\n');
+    }
+    event.push('\n');
+
+    Bytes::from(event)
+}
+
+fn status_event(status: Option<String>) -> Bytes {
+    let data = json!({ "status": status });
+    Bytes::from(format!("event: status\ndata: {}\n\n", data))
+}
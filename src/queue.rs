@@ -0,0 +1,196 @@
+use futures::lock::{Mutex as AsyncMutex, MutexGuard};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::core::{self, Deployment, Project};
+use crate::errors::SubiloError;
+use crate::job::{Handle, Witness};
+
+/// What to do with a new job when the project already has one queued or
+/// running. Jobs of the same project never run at the same time.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Policy {
+    /// Run after the previous jobs
+    #[default]
+    Queue,
+    /// Cancel the previous jobs and run after them
+    CancelPrevious,
+    /// Do not create the job
+    Reject,
+}
+
+struct Entry {
+    id: String,
+    project: String,
+    handle: Arc<Handle>,
+}
+
+struct Pending {
+    entry: Entry,
+    deployment: Deployment,
+    witness: Witness,
+}
+
+#[derive(Default)]
+struct State {
+    running: Vec<Entry>,
+    pending: VecDeque<Pending>,
+}
+
+/// Schedules jobs on their own threads, at most `max_jobs` at a time and one
+/// at a time per project.
+#[derive(Clone)]
+pub struct Queue {
+    max_jobs: usize,
+    state: Arc<Mutex<State>>,
+    admission: Arc<AsyncMutex<()>>,
+}
+
+impl Queue {
+    pub fn new(max_jobs: usize) -> Self {
+        Self {
+            max_jobs,
+            state: Arc::new(Mutex::new(State::default())),
+            admission: Arc::new(AsyncMutex::new(())),
+        }
+    }
+
+    /// Applies the project's concurrency policy to a new job. The returned
+    /// guard has to be held until the job is submitted, so that concurrent
+    /// requests for the same project are admitted one after the other.
+    pub async fn admit(&self, project: &Project) -> Result<MutexGuard<'_, ()>, SubiloError> {
+        let guard = self.admission.lock().await;
+
+        let previous: Vec<String> = {
+            let state = self.lock();
+            state
+                .running
+                .iter()
+                .chain(state.pending.iter().map(|pending| &pending.entry))
+                .filter(|entry| entry.project == project.name)
+                .map(|entry| entry.id.clone())
+                .collect()
+        };
+
+        match project.concurrency {
+            Policy::Queue => {}
+            Policy::Reject if !previous.is_empty() => {
+                debug!("Rejecting job, project '{}' is busy", &project.name);
+                return Err(SubiloError::ProjectBusy {
+                    name: project.name.clone(),
+                });
+            }
+            Policy::Reject => {}
+            Policy::CancelPrevious => {
+                for id in previous {
+                    debug!("Cancelling previous job '{}'", &id);
+                    self.cancel(&id);
+                }
+            }
+        }
+
+        Ok(guard)
+    }
+
+    pub fn submit(&self, deployment: Deployment, witness: Witness) {
+        let entry = Entry {
+            id: witness.id().to_owned(),
+            project: deployment.project.name.clone(),
+            handle: witness.handle(),
+        };
+
+        self.lock().pending.push_back(Pending {
+            entry,
+            deployment,
+            witness,
+        });
+
+        self.schedule();
+    }
+
+    /// Cancels a job that has not finished yet. Queued jobs are taken out of
+    /// the queue and end right away instead of waiting for their turn.
+    pub fn cancel(&self, id: &str) -> bool {
+        let mut state = self.lock();
+
+        if let Some(entry) = state.running.iter().find(|entry| entry.id == id) {
+            entry.handle.cancel();
+            return true;
+        }
+
+        match state
+            .pending
+            .iter()
+            .position(|pending| pending.entry.id == id)
+        {
+            Some(position) => {
+                let pending = state.pending.remove(position).unwrap();
+                pending.entry.handle.cancel();
+                self.spawn(pending.deployment, pending.witness);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn schedule(&self) {
+        let mut state = self.lock();
+
+        while state.running.len() < self.max_jobs {
+            let running = &state.running;
+            let next = state.pending.iter().position(|pending| {
+                !running
+                    .iter()
+                    .any(|entry| entry.project == pending.entry.project)
+            });
+
+            match next {
+                Some(position) => {
+                    let pending = state.pending.remove(position).unwrap();
+                    state.running.push(pending.entry);
+                    self.spawn(pending.deployment, pending.witness);
+                }
+                None => break,
+            }
+        }
+    }
+
+    fn finish(&self, id: &str) {
+        self.lock().running.retain(|entry| entry.id != id);
+        self.schedule();
+    }
+
+    fn spawn(&self, deployment: Deployment, witness: Witness) {
+        let queue = self.clone();
+        let id = witness.id().to_owned();
+
+        debug!(
+            "Spawning thread to run deployment for project {}",
+            &deployment.project.name
+        );
+        thread::spawn(move || {
+            let project_name = deployment.project.name.clone();
+            let result = core::run_project_deployment(deployment, witness);
+
+            match result {
+                Ok(_) => debug!(
+                    "Deployment for project {} processed successfully",
+                    project_name
+                ),
+                Err(err) => error!(
+                    "Failed running deployment for project {}.\nWith error:\n{}",
+                    project_name, err
+                ),
+            }
+
+            queue.finish(&id);
+        });
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().expect("Failed to lock jobs queue")
+    }
+}
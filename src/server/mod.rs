@@ -0,0 +1,186 @@
+use actix_http::{Error, HttpService, Protocol, Request, Response};
+use actix_rt::net::{TcpStream, UnixStream};
+use actix_service::{fn_service, map_config, pipeline_factory, IntoServiceFactory};
+use actix_service::{Service, ServiceFactory};
+use actix_web::dev::{AppConfig, MessageBody};
+use futures::future::ok;
+use std::fs;
+use std::io;
+use std::marker::PhantomData;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::UnixListener;
+use std::path::{Path, PathBuf};
+use std::{fmt, net};
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+
+use crate::errors::SubiloError;
+
+mod tls;
+
+pub use tls::Certificates;
+
+/// Address the agent listens on
+#[derive(Debug, Clone, PartialEq)]
+pub enum Bind {
+    Tcp(net::SocketAddr),
+    Unix(PathBuf),
+}
+
+impl Bind {
+    /// Parses an IP address, with or without port, or a `unix:` socket path.
+    /// Addresses without port use the given one.
+    pub fn parse(address: &str, port: u16) -> Result<Self, SubiloError> {
+        if let Some(path) = address.strip_prefix("unix:") {
+            if path.is_empty() {
+                return Err(SubiloError::InvalidBind {
+                    address: address.to_owned(),
+                });
+            }
+            return Ok(Bind::Unix(PathBuf::from(shellexpand::tilde(path).as_ref())));
+        }
+
+        if let Ok(socket) = address.parse::<net::SocketAddr>() {
+            return Ok(Bind::Tcp(socket));
+        }
+
+        // IPv6 addresses can be written in brackets without port, e.g. [::1]
+        let ip = address.trim_start_matches('[').trim_end_matches(']');
+        match ip.parse::<net::IpAddr>() {
+            Ok(ip) => Ok(Bind::Tcp(net::SocketAddr::new(ip, port))),
+            Err(_) => Err(SubiloError::InvalidBind {
+                address: address.to_owned(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Bind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Bind::Tcp(socket) => write!(f, "{}", socket),
+            Bind::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// Serves the agent application on plain and TLS listeners.
+///
+/// `actix_web::HttpServer` only supports TLS through its own dependencies,
+/// so the HTTP service is set up here, the same way it does.
+pub struct Server<F, I, S, B> {
+    factory: F,
+    builder: actix_server::ServerBuilder,
+    _phantom: PhantomData<(I, S, B)>,
+}
+
+impl<F, I, S, B> Server<F, I, S, B>
+where
+    F: Fn() -> I + Send + Clone + 'static,
+    I: IntoServiceFactory<S>,
+    S: ServiceFactory<Config = AppConfig, Request = Request>,
+    S::Error: Into<Error> + 'static,
+    S::InitError: fmt::Debug,
+    S::Response: Into<Response<B>> + 'static,
+    <S::Service as Service>::Future: 'static,
+    B: MessageBody + 'static,
+{
+    pub fn new(factory: F) -> Self {
+        Self {
+            factory,
+            builder: actix_server::Server::build(),
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn listen(mut self, listener: net::TcpListener) -> io::Result<Self> {
+        let factory = self.factory.clone();
+        let addr = listener.local_addr()?;
+
+        self.builder = self
+            .builder
+            .listen(format!("subilo-{}", addr), listener, move || {
+                HttpService::build()
+                    .local_addr(addr)
+                    .finish(map_config(factory(), |_| AppConfig::default()))
+                    .tcp()
+            })?;
+
+        Ok(self)
+    }
+
+    pub fn listen_tls(mut self, listener: net::TcpListener, tls: ServerConfig) -> io::Result<Self> {
+        let factory = self.factory.clone();
+        let addr = listener.local_addr()?;
+        let acceptor = TlsAcceptor::from(std::sync::Arc::new(tls));
+
+        self.builder =
+            self.builder
+                .listen(format!("subilo-tls-{}", addr), listener, move || {
+                    let acceptor = acceptor.clone();
+                    let service = HttpService::build()
+                        .local_addr(addr)
+                        .finish(map_config(factory(), |_| AppConfig::default()))
+                        .map_err(|err| debug!("Failed to serve TLS connection. Error: {:?}", err));
+
+                    pipeline_factory(fn_service(move |io: TcpStream| {
+                        let acceptor = acceptor.clone();
+                        async move {
+                            acceptor
+                                .accept(io)
+                                .await
+                                .map_err(|err| debug!("TLS handshake failed. Error: {}", err))
+                        }
+                    }))
+                    .and_then(|io: TlsStream<TcpStream>| {
+                        let peer_addr = io.get_ref().0.peer_addr().ok();
+                        ok((io, Protocol::Http1, peer_addr))
+                    })
+                    .and_then(service)
+                })?;
+
+        Ok(self)
+    }
+
+    pub fn listen_uds(mut self, listener: UnixListener) -> io::Result<Self> {
+        let factory = self.factory.clone();
+        let name = format!("subilo-{:?}", listener.local_addr()?);
+
+        self.builder = self.builder.listen_uds(name, listener, move || {
+            pipeline_factory(|io: UnixStream| ok((io, Protocol::Http1, None))).and_then(
+                HttpService::build().finish(map_config(factory(), |_| AppConfig::default())),
+            )
+        })?;
+
+        Ok(self)
+    }
+
+    pub fn run(self) -> actix_server::Server { self.builder.run() }
+}
+
+/// Binds a Unix domain socket, replacing the socket file left by a previous
+/// run, and sets its file mode
+pub fn bind_uds(path: &Path, mode: Option<u32>) -> io::Result<UnixListener> {
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if metadata.file_type().is_socket() {
+            fs::remove_file(path)?;
+        }
+    }
+
+    let mode = match mode {
+        Some(mode) => mode,
+        None => return UnixListener::bind(path),
+    };
+
+    // Only the agent's user can connect until the mode is set. The umask is
+    // changed while nothing else runs, before the server starts
+    let umask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(path);
+    unsafe { libc::umask(umask) };
+
+    let listener = listener?;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+
+    Ok(listener)
+}
@@ -0,0 +1,93 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::{Arc, RwLock};
+use tokio_rustls::rustls::internal::pemfile;
+use tokio_rustls::rustls::sign::{self, CertifiedKey};
+use tokio_rustls::rustls::{ClientHello, NoClientAuth, ResolvesServerCert, ServerConfig};
+
+use crate::errors::SubiloError;
+
+/// TLS certificate and private key, read from PEM files and reloaded in place
+/// so open connections and running jobs are not affected.
+pub struct Certificates {
+    pub cert_path: String,
+    pub key_path: String,
+    current: RwLock<CertifiedKey>,
+}
+
+impl Certificates {
+    pub fn load(cert_path: &str, key_path: &str) -> Result<Arc<Self>, SubiloError> {
+        let certified_key = read_certified_key(cert_path, key_path)?;
+
+        Ok(Arc::new(Self {
+            cert_path: cert_path.to_owned(),
+            key_path: key_path.to_owned(),
+            current: RwLock::new(certified_key),
+        }))
+    }
+
+    /// Reads the files again, the current certificate is kept if they are not
+    /// valid
+    pub fn reload(&self) -> Result<(), SubiloError> {
+        let certified_key = read_certified_key(&self.cert_path, &self.key_path)?;
+        *self.current.write().expect("Certificates lock poisoned") = certified_key;
+
+        Ok(())
+    }
+
+    /// Server configuration that serves the current certificate on every
+    /// handshake
+    pub fn server_config(self: &Arc<Self>) -> ServerConfig {
+        let mut config = ServerConfig::new(NoClientAuth::new());
+        config.cert_resolver = self.clone();
+        config.set_protocols(&[b"http/1.1".to_vec()]);
+
+        config
+    }
+}
+
+impl ResolvesServerCert for Certificates {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<CertifiedKey> {
+        self.current.read().ok().map(|current| current.clone())
+    }
+}
+
+/// Reads a PEM certificate chain and private key, PKCS#8 or RSA
+fn read_certified_key(cert_path: &str, key_path: &str) -> Result<CertifiedKey, SubiloError> {
+    let open = |path: &str| {
+        let path = shellexpand::tilde(path).into_owned();
+        File::open(&path)
+            .map(BufReader::new)
+            .map_err(|err| SubiloError::ReadTlsFile { path, source: err })
+    };
+    let invalid = |path: &str, message: &str| SubiloError::InvalidTlsFile {
+        path: path.to_owned(),
+        message: message.to_owned(),
+    };
+
+    let certs = pemfile::certs(&mut open(cert_path)?)
+        .map_err(|_| invalid(cert_path, "not a PEM certificate"))?;
+    if certs.is_empty() {
+        return Err(invalid(cert_path, "no certificate found"));
+    }
+
+    let mut keys = pemfile::pkcs8_private_keys(&mut open(key_path)?)
+        .map_err(|_| invalid(key_path, "not a PEM private key"))?;
+    if keys.is_empty() {
+        keys = pemfile::rsa_private_keys(&mut open(key_path)?)
+            .map_err(|_| invalid(key_path, "not a PEM private key"))?;
+    }
+    let key = match keys.first() {
+        Some(key) => key,
+        None => return Err(invalid(key_path, "no private key found")),
+    };
+
+    let signing_key = sign::any_supported_type(key)
+        .map_err(|_| invalid(key_path, "unsupported private key type"))?;
+    let certified_key = CertifiedKey::new(certs, Arc::new(signing_key));
+    certified_key
+        .cross_check_end_entity_cert(None)
+        .map_err(|err| invalid(cert_path, &err.to_string()))?;
+
+    Ok(certified_key)
+}
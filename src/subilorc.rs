@@ -0,0 +1,122 @@
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::sync::{Arc, RwLock};
+
+use crate::core::{Project, ProjectInfo};
+use crate::errors::SubiloError;
+use crate::{config, JobsConfig, ProjectsInfo};
+
+/// Projects configuration read from `.subilorc`. It is loaded once and
+/// replaced as a whole when the file is reloaded, so a request always sees
+/// one version of it.
+#[derive(Clone)]
+pub struct Subilorc {
+    path: String,
+    current: Arc<RwLock<Arc<Projects>>>,
+    reload_error: Arc<RwLock<Option<ReloadError>>>,
+}
+
+pub struct Projects {
+    pub projects: Vec<Project>,
+    pub info: Vec<ProjectInfo>,
+    pub loaded_at: String,
+}
+
+impl Projects {
+    pub fn find(&self, name: &str) -> Option<&Project> {
+        self.projects.iter().find(|project| project.name == name)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReloadError {
+    pub message: String,
+    pub failed_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Status {
+    pub path: String,
+    pub loaded_at: String,
+    /// Error of the last reload, if it failed
+    pub reload_error: Option<ReloadError>,
+}
+
+impl Subilorc {
+    pub fn load(path: &str) -> Result<Self, SubiloError> {
+        let projects = read(path)?;
+
+        Ok(Self {
+            path: path.to_owned(),
+            current: Arc::new(RwLock::new(Arc::new(projects))),
+            reload_error: Arc::new(RwLock::new(None)),
+        })
+    }
+
+    /// Current version of the projects
+    pub fn projects(&self) -> Arc<Projects> {
+        self.current.read().expect("Subilorc lock poisoned").clone()
+    }
+
+    /// Reads the file again. If it is not valid the current projects are
+    /// kept and the error is recorded until the next successful reload.
+    pub fn reload(&self) -> Result<(), SubiloError> {
+        match read(&self.path) {
+            Ok(projects) => {
+                *self.current.write().expect("Subilorc lock poisoned") = Arc::new(projects);
+                *self.reload_error.write().expect("Subilorc lock poisoned") = None;
+                Ok(())
+            }
+            Err(err) => {
+                *self.reload_error.write().expect("Subilorc lock poisoned") = Some(ReloadError {
+                    message: err.to_string(),
+                    failed_at: now(),
+                });
+                Err(err)
+            }
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        Status {
+            path: self.path.clone(),
+            loaded_at: self.projects().loaded_at.clone(),
+            reload_error: self
+                .reload_error
+                .read()
+                .expect("Subilorc lock poisoned")
+                .clone(),
+        }
+    }
+}
+
+fn read(path: &str) -> Result<Projects, SubiloError> {
+    let content =
+        fs::read_to_string(path).map_err(|err| SubiloError::ReadSubiloRC { source: err })?;
+
+    let jobs_config: JobsConfig =
+        toml::from_str(&content).map_err(|err| SubiloError::ParseSubiloRC { source: err })?;
+    let projects_info: ProjectsInfo =
+        toml::from_str(&content).map_err(|err| SubiloError::ParseSubiloRC { source: err })?;
+    // The agent settings are only read on start, but a broken [agent]
+    // section would prevent the next start
+    config::Config::parse(&content)?;
+
+    let mut names = HashSet::new();
+    for project in &jobs_config.projects {
+        if !names.insert(project.name.as_str()) {
+            return Err(SubiloError::DuplicateProject {
+                name: project.name.clone(),
+            });
+        }
+    }
+
+    Ok(Projects {
+        projects: jobs_config.projects,
+        info: projects_info.projects,
+        loaded_at: now(),
+    })
+}
+
+fn now() -> String { chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true) }
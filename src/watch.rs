@@ -0,0 +1,68 @@
+use actix_rt::time::interval;
+use futures::future::{self, Either};
+use futures::pin_mut;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use tokio::signal::unix::{signal, SignalKind};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Calls `reload` when the agent receives SIGHUP or when any of the files
+/// changes. Files are checked every couple of seconds.
+pub fn watch<F>(name: &'static str, paths: Vec<PathBuf>, reload: F)
+where
+    F: Fn() + 'static,
+{
+    actix_rt::spawn(async move {
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => Some(hangup),
+            Err(err) => {
+                error!(
+                    "Failed to listen to SIGHUP to reload {}. Error: {}",
+                    name, err
+                );
+                None
+            }
+        };
+        let mut ticks = interval(POLL_INTERVAL);
+        let mut modified = modified_times(&paths);
+
+        loop {
+            let hangup_received = match hangup.as_mut() {
+                Some(hangup) => Either::Left(hangup.recv()),
+                None => Either::Right(future::pending()),
+            };
+
+            let tick = ticks.tick();
+            pin_mut!(hangup_received, tick);
+
+            match future::select(hangup_received, tick).await {
+                Either::Left(_) => {
+                    info!("Received SIGHUP, reloading {}", name);
+                    modified = modified_times(&paths);
+                    reload();
+                }
+                Either::Right(_) => {
+                    let current = modified_times(&paths);
+                    if current != modified {
+                        info!("{} changed, reloading", name);
+                        modified = current;
+                        reload();
+                    }
+                }
+            }
+        }
+    });
+}
+
+fn modified_times(paths: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    paths
+        .iter()
+        .map(|path| {
+            fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        })
+        .collect()
+}